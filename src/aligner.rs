@@ -0,0 +1,288 @@
+use crate::{
+    alignment::{
+        AlignmentMode, AlignmentOptions, AlignmentStats, Band, SeqGraphAlignment, TieBreak,
+    },
+    consensus::{ConsensusPath, MsaRow},
+    error::PoaError,
+    graph::{GraphError, POAGraph, Token},
+    scoring::{Scorer, Scoring},
+};
+
+/// Order the records given together are added to the graph in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SequenceOrder {
+    /// As they are given
+    #[default]
+    Given,
+    /// Longest first, records of the same length as they are given
+    LongestFirst,
+}
+
+/// Options of a `PoaAligner`, from `PoaAligner::builder()`
+#[derive(Debug, Clone)]
+pub struct PoaAlignerBuilder<S = Scoring> {
+    scorer: S,
+    options: AlignmentOptions,
+    order: SequenceOrder,
+}
+
+impl Default for PoaAlignerBuilder {
+    fn default() -> Self {
+        Self {
+            scorer: Scoring::default(),
+            options: AlignmentOptions::default(),
+            order: SequenceOrder::default(),
+        }
+    }
+}
+
+impl<S> PoaAlignerBuilder<S> {
+    /// Score tokens with the given scorer instead of the default `Scoring`
+    pub fn scorer<R>(self, scorer: R) -> PoaAlignerBuilder<R> {
+        PoaAlignerBuilder {
+            scorer,
+            options: self.options,
+            order: self.order,
+        }
+    }
+
+    pub fn mode(mut self, mode: AlignmentMode) -> Self {
+        self.options.mode = mode;
+        self
+    }
+
+    pub fn band(mut self, band: Band) -> Self {
+        self.options.band = band;
+        self
+    }
+
+    pub fn max_memory(mut self, bytes: usize) -> Self {
+        self.options.max_memory = Some(bytes);
+        self
+    }
+
+    pub fn anchor(mut self, length: usize) -> Self {
+        self.options.anchor = Some(length);
+        self
+    }
+
+    pub fn tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.options.tie_break = tie_break;
+        self
+    }
+
+    /// Replace all of the alignment options
+    pub fn options(mut self, options: AlignmentOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn order(mut self, order: SequenceOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Aligner with an empty graph
+    pub fn build<T: Token>(self) -> PoaAligner<T, S>
+    where
+        S: Scorer<T>,
+    {
+        PoaAligner {
+            scorer: self.scorer,
+            options: self.options,
+            order: self.order,
+            graph: POAGraph::empty(),
+        }
+    }
+}
+
+/// Graph that sequences are aligned to and added one at a time, with the
+/// options they are aligned with
+pub struct PoaAligner<T: Token = String, S = Scoring> {
+    scorer: S,
+    options: AlignmentOptions,
+    order: SequenceOrder,
+    graph: POAGraph<T>,
+}
+
+impl PoaAligner {
+    pub fn builder() -> PoaAlignerBuilder {
+        PoaAlignerBuilder::default()
+    }
+}
+
+impl<T: Token, S: Scorer<T>> PoaAligner<T, S> {
+    /// Align the tokens to the graph and add them, giving the stats of their
+    /// alignment. None for the first sequence, which starts the graph.
+    pub fn add(
+        &mut self,
+        label: impl Into<String>,
+        tokens: Vec<T>,
+    ) -> Result<Option<AlignmentStats>, PoaError> {
+        self.add_with(label, tokens, |_, _| Ok(()))
+    }
+
+    /// As `add`, showing `inspect` the alignment and the aligner before the
+    /// alignment is added. The first sequence is not aligned.
+    pub fn add_with(
+        &mut self,
+        label: impl Into<String>,
+        tokens: Vec<T>,
+        inspect: impl FnOnce(&SeqGraphAlignment<T>, &Self) -> Result<(), PoaError>,
+    ) -> Result<Option<AlignmentStats>, PoaError> {
+        if self.graph.graph.node_count() == 0 {
+            return self
+                .graph
+                .add_sequence(label.into(), tokens, &self.scorer, self.options);
+        }
+        let aln = self.align(label, tokens)?;
+        inspect(&aln, self)?;
+        let stats = aln.stats();
+        self.graph.add_alignment(aln, &self.scorer);
+        Ok(Some(stats))
+    }
+
+    /// Align the tokens to the graph without adding them
+    pub fn align(
+        &self,
+        label: impl Into<String>,
+        tokens: Vec<T>,
+    ) -> Result<SeqGraphAlignment<T>, PoaError> {
+        self.graph
+            .align(label.into(), tokens, &self.scorer, self.options)
+    }
+
+    /// Up to `count` alignments of the tokens scoring as well as the best
+    /// one, see `SeqGraphAlignment::co_optimal`
+    pub fn co_optimal(
+        &self,
+        label: impl Into<String>,
+        tokens: Vec<T>,
+        count: usize,
+    ) -> Result<Vec<SeqGraphAlignment<T>>, PoaError> {
+        Ok(SeqGraphAlignment::co_optimal(
+            label.into(),
+            tokens,
+            &self.graph.graph,
+            &self.scorer,
+            self.options,
+            count,
+        )?)
+    }
+
+    /// Align each of the records to the graph in parallel without adding
+    /// them, in the order of the records
+    pub fn align_batch(
+        &self,
+        records: Vec<(String, Vec<T>)>,
+    ) -> Result<Vec<SeqGraphAlignment<T>>, PoaError>
+    where
+        T: Send + Sync,
+        S: Sync,
+    {
+        Ok(SeqGraphAlignment::align_batch_with_paths(
+            records,
+            &self.graph.graph,
+            Some(&self.graph.paths),
+            &self.scorer,
+            self.options,
+        )?)
+    }
+
+    /// Add the records in the order of the aligner, giving the labels and
+    /// stats in the order they were added
+    pub fn add_all(
+        &mut self,
+        mut records: Vec<(String, Vec<T>)>,
+    ) -> Result<Vec<(String, Option<AlignmentStats>)>, PoaError> {
+        if self.order == SequenceOrder::LongestFirst {
+            records.sort_by_key(|(_, tokens)| std::cmp::Reverse(tokens.len()));
+        }
+        records
+            .into_iter()
+            .map(|(label, tokens)| {
+                let stats = self.add(label.clone(), tokens)?;
+                Ok((label, stats))
+            })
+            .collect()
+    }
+
+    pub fn msa(&self) -> Result<Vec<MsaRow<T>>, GraphError> {
+        self.graph.msa()
+    }
+
+    pub fn consensus(&self) -> Result<ConsensusPath<T>, GraphError> {
+        self.graph.consensus()
+    }
+
+    pub fn graph(&self) -> &POAGraph<T> {
+        &self.graph
+    }
+
+    pub fn into_graph(self) -> POAGraph<T> {
+        self.graph
+    }
+}
+
+#[cfg(test)]
+mod aligner_tests {
+    use super::*;
+    use crate::{scoring::EditDistance, test_utils::to_seq};
+
+    #[test]
+    fn incremental_test() {
+        let mut aligner = PoaAligner::builder().build();
+        assert!(aligner.msa().unwrap().is_empty());
+
+        assert_eq!(aligner.add("seq_1", to_seq("ACGT")).unwrap(), None);
+        let stats = aligner.add("seq_2", to_seq("AGT")).unwrap().unwrap();
+        assert_eq!((stats.matches, stats.deletions), (3, 1));
+        aligner.add("seq_3", to_seq("ACGT")).unwrap();
+
+        assert_eq!(aligner.consensus().unwrap().items, to_seq("ACGT"));
+        let msa = aligner.msa().unwrap();
+        assert_eq!(msa.len(), 3);
+        assert_eq!(msa[1].1[1], None);
+        assert_eq!(aligner.graph().labels, vec!["seq_1", "seq_2", "seq_3"]);
+
+        // the alignment is shown before it is added
+        let mut shown = None;
+        aligner
+            .add_with("seq_4", to_seq("ACG"), |aln, aligner| {
+                assert_eq!(aligner.graph().labels.len(), 3);
+                shown = Some(aln.stats());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(shown.unwrap().deletions, 1);
+        assert_eq!(aligner.into_graph().labels.len(), 4);
+    }
+
+    #[test]
+    fn builder_test() {
+        let mut aligner = PoaAligner::builder()
+            .scorer(EditDistance::new(Scoring::default(), 0.5))
+            .mode(AlignmentMode::Local)
+            .tie_break(TieBreak::LeftGaps)
+            .order(SequenceOrder::LongestFirst)
+            .build();
+        assert_eq!(aligner.options.mode, AlignmentMode::Local);
+        assert_eq!(aligner.options.tie_break, TieBreak::LeftGaps);
+
+        let records = vec![
+            ("short".to_string(), to_seq("CG")),
+            ("long".to_string(), to_seq("ACGT")),
+            ("same".to_string(), to_seq("CG")),
+        ];
+        let added = aligner.add_all(records).unwrap();
+        let labels: Vec<_> = added.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(labels, vec!["long", "short", "same"]);
+        assert!(added[0].1.is_none());
+        assert!(added[1].1.is_some());
+
+        assert!(matches!(
+            aligner.add("empty", vec![]),
+            Err(PoaError::EmptyInput { .. })
+        ));
+    }
+}
@@ -1,160 +1,1275 @@
-use crate::graph::{EdgeData, NodeData};
-use ndarray::Array2;
+use crate::{
+    anchors::{align_anchored, sequence_paths},
+    graph::{topological_order, EdgeData, GraphError, NodeData, Token},
+    scoring::Scorer,
+    simd::Simd,
+};
 use petgraph::{
-    algo::toposort,
     graph::{DiGraph, NodeIndex},
-    visit::EdgeRef,
     Direction,
 };
-use std::cmp::max;
-use std::collections::HashMap;
-
-const MATCHSCORE: i32 = 1;
-const MISMATCHSCORE: i32 = -1;
-const GAP: i32 = -2;
-
-type Matrix = Array2<i32>;
-
-// #[derive(Debug)]
-// enum Move {
-//     Insertion,
-//     Deletion,
-//     Match,
-// }
-
-#[derive(Debug, Eq)]
-struct Candidate {
-    score: i32,
-    graph_pos: usize,
-    seq_pos: usize,
+use rayon::prelude::*;
+use std::{
+    cmp::{max, min},
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+/// Score of an alignment with the sequence positions and graph nodes
+/// aligned to each other, `None` marks a gap
+pub(crate) type Traceback = (i32, Vec<Option<i32>>, Vec<Option<NodeIndex>>);
+
+/// Stands in for minus infinity, leaves head room so that adding
+/// penalties to it cannot overflow
+pub(crate) const NEG_INF: i32 = i32::MIN / 2;
+
+/// Which ends of the sequence and the graph must be aligned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlignmentMode {
+    /// Align the whole sequence to a full path through the graph
+    #[default]
+    Global,
+    /// Align the best scoring part of the sequence to part of the graph
+    Local,
+    /// Align the whole sequence to any part of a path, leading and
+    /// trailing graph nodes are free
+    Fitting,
+    /// Align part of the sequence to a full path, leading and trailing
+    /// sequence tokens are free
+    Containment,
+    /// Align a suffix of either the sequence or the graph to a prefix of
+    /// the other
+    Overlap,
+}
+
+impl AlignmentMode {
+    /// Score of starting an alignment at a cell, zero where the mode allows
+    /// an alignment to start
+    fn start(self, i: usize, j: usize) -> i32 {
+        let allowed = match self {
+            AlignmentMode::Global => i == 0 && j == 0,
+            AlignmentMode::Local => true,
+            AlignmentMode::Fitting => j == 0,
+            AlignmentMode::Containment => i == 0,
+            AlignmentMode::Overlap => i == 0 || j == 0,
+        };
+        if allowed {
+            0
+        } else {
+            NEG_INF
+        }
+    }
+}
+
+/// Which cells of the DP are computed for each node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Band {
+    /// Every cell
+    #[default]
+    Full,
+    /// Cells within the given number of columns of the diagonal, the depth
+    /// of each node in the graph scaled to the sequence length
+    Fixed(usize),
+    /// Cells within the given number of columns of where the predecessors
+    /// of each node scored best, following the alignment as it goes
+    /// (abPOA style)
+    Adaptive(usize),
+}
+
+impl Band {
+    /// The band about twice as wide, the full DP once it would span all
+    /// `width` columns
+    fn widen(self, width: usize) -> Self {
+        match self {
+            Band::Fixed(w) if 2 * w + 1 < width => Band::Fixed(2 * w + 1),
+            Band::Adaptive(w) if 2 * w + 1 < width => Band::Adaptive(2 * w + 1),
+            _ => Band::Full,
+        }
+    }
+}
+
+/// Which of equally scoring alignments is taken, walking back from where
+/// the alignment ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TieBreak {
+    /// Gaps are taken before matches, placing gaps as far right as they go
+    #[default]
+    RightGaps,
+    /// Matches are taken before gaps, placing gaps as far left as they go
+    /// and preferring a mismatch to a pair of gaps
+    LeftGaps,
+    /// Identical tokens are aligned before gaps, which are placed as with
+    /// `RightGaps`, and mismatches are taken last
+    Matches,
+}
+
+/// How sequences are aligned to the graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AlignmentOptions {
+    pub mode: AlignmentMode,
+    /// The band is widened, up to the full DP, until no alignment leaving
+    /// it could score better than the one found within it
+    pub band: Band,
+    /// Bytes the full DP may use, beyond it rows are checkpointed and
+    /// filled again while backtracking
+    pub max_memory: Option<usize>,
+    /// Length of the exact matches global alignments are anchored at, the
+    /// DP then only runs between them
+    pub anchor: Option<usize>,
+    /// Which of equally scoring alignments is taken
+    pub tie_break: TieBreak,
+}
+
+impl From<AlignmentMode> for AlignmentOptions {
+    fn from(mode: AlignmentMode) -> Self {
+        Self {
+            mode,
+            ..Default::default()
+        }
+    }
 }
 
-// Implementing PartialEq is necessary to use PartialOrd
-impl PartialEq for Candidate {
-    fn eq(&self, other: &Self) -> bool {
-        (self.score, self.graph_pos, self.seq_pos) == (other.score, other.graph_pos, other.seq_pos)
+/// The last move of an alignment ending at a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Move {
+    /// sequence token aligned to a graph node
+    Match,
+    /// sequence token aligned to a gap
+    Insertion,
+    /// graph node aligned to a gap
+    Deletion,
+}
+
+/// What the backtrack does at the next cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    /// Any move giving the best score of the cell, except for one extending
+    /// the gap that was just opened
+    Best(Option<Move>),
+    /// Carry on with the gap of the move, the cell scored from its matrix
+    Extend(Move),
+}
+
+/// Cell of the backtrack and what it does there
+type State = (usize, usize, Pending);
+
+/// One way of walking back from a cell keeping the score
+enum Step {
+    /// The alignment starts at the cell
+    Start,
+    /// Align a sequence position and a node, either may be a gap, and carry
+    /// on from the state
+    Pair(Option<i32>, Option<NodeIndex>, State),
+}
+
+/// Columns present in both ranges, if any
+fn overlap(a: &Range<usize>, b: &Range<usize>) -> Option<Range<usize>> {
+    let columns = max(a.start, b.start)..min(a.end, b.end);
+    (!columns.is_empty()).then_some(columns)
+}
+
+/// Scores of one row of the DP for the columns within its band, laid out as
+/// the matches, insertions, deletions and best scores one after another
+#[derive(Clone)]
+struct DpRow {
+    columns: Range<usize>,
+    cells: Vec<i32>,
+}
+
+impl DpRow {
+    fn matrix(&self, mv: Option<Move>) -> &[i32] {
+        let n = self.columns.len();
+        let k = match mv {
+            Some(Move::Match) => 0,
+            Some(Move::Insertion) => 1,
+            Some(Move::Deletion) => 2,
+            None => 3,
+        };
+        &self.cells[k * n..(k + 1) * n]
+    }
+
+    fn deletions(&self) -> &[i32] {
+        self.matrix(Some(Move::Deletion))
+    }
+
+    fn bests(&self) -> &[i32] {
+        self.matrix(None)
+    }
+}
+
+/// Gotoh style score matrices, one per move, along with the best of them
+/// for each cell. Rows are the nodes in topological order (row 0 is a
+/// virtual start node) and columns are the sequence positions (column 0 is
+/// the empty prefix). Rows only store the columns within their band, the
+/// cells outside it are unreachable. Rows may be left out or dropped when
+/// aligning within a memory limit.
+struct DpMatrices {
+    rows: Vec<Option<DpRow>>,
+    /// Rows from here on have all their predecessors stored
+    complete_from: usize,
+    /// Column of the best scoring cell of each filled row
+    best_columns: Vec<usize>,
+    /// Score, row and column of the best cell an alignment may end at
+    end: (i32, usize, usize),
+    /// Number of columns of a full row
+    width: usize,
+    mode: AlignmentMode,
+    tie_break: TieBreak,
+    simd: Simd,
+}
+
+impl DpMatrices {
+    fn new(options: AlignmentOptions, height: usize, width: usize) -> Self {
+        Self {
+            rows: vec![None; height],
+            complete_from: 0,
+            best_columns: vec![0; height],
+            end: (NEG_INF, 0, 0),
+            width,
+            mode: options.mode,
+            tie_break: options.tie_break,
+            simd: Simd::detect(),
+        }
+    }
+
+    fn row(&self, i: usize) -> &DpRow {
+        self.rows[i].as_ref().expect("DP row is stored")
     }
+
+    fn cell(&self, mv: Option<Move>, i: usize, j: usize) -> i32 {
+        let row = self.row(i);
+        if row.columns.contains(&j) {
+            row.matrix(mv)[j - row.columns.start]
+        } else {
+            NEG_INF
+        }
+    }
+
+    fn get(&self, mv: Move, i: usize, j: usize) -> i32 {
+        self.cell(Some(mv), i, j)
+    }
+
+    /// Score of starting an alignment at the cell, zero where the mode
+    /// allows an alignment to start
+    fn start(&self, i: usize, j: usize) -> i32 {
+        self.mode.start(i, j)
+    }
+
+    /// Whether the mode allows an alignment to end in column `j` at a node,
+    /// `terminal` tells if the node has no successors
+    fn can_end(&self, j: usize, terminal: bool) -> bool {
+        let last_column = j + 1 == self.width;
+        match self.mode {
+            AlignmentMode::Global => terminal && last_column,
+            AlignmentMode::Local => true,
+            AlignmentMode::Fitting => last_column,
+            AlignmentMode::Containment => terminal,
+            AlignmentMode::Overlap => terminal || last_column,
+        }
+    }
+
+    /// Best score of any alignment ending at a filled cell
+    fn best(&self, i: usize, j: usize) -> i32 {
+        self.cell(None, i, j)
+    }
+
+    /// Fill row `i` from the best deletion and match scores of each of its
+    /// columns, insertions then run along the row. `terminal` tells if the
+    /// node of the row has no successors.
+    #[allow(clippy::too_many_arguments)]
+    fn push_row(
+        &mut self,
+        i: usize,
+        columns: Range<usize>,
+        deletion: &[i32],
+        matching: &[i32],
+        gap_open: i32,
+        gap_extend: i32,
+        terminal: bool,
+    ) {
+        let n = columns.len();
+        let mut cells = Vec::with_capacity(4 * n);
+        cells.extend_from_slice(matching);
+        cells.resize(2 * n, NEG_INF);
+        cells.extend_from_slice(deletion);
+        // the best of matching and deleting is known for the whole row,
+        // insertions are added along it
+        cells.extend_from_slice(matching);
+        let (front, bests) = cells.split_at_mut(3 * n);
+        self.simd.max_assign(bests, deletion);
+        // an alignment may start anywhere in the row when it may start past
+        // its first column
+        if self.mode.start(i, 1) == 0 {
+            for best in bests.iter_mut() {
+                *best = max(*best, 0);
+            }
+        } else if columns.start == 0 {
+            bests[0] = max(bests[0], self.mode.start(i, 0));
+        }
+        self.simd
+            .insertions(&mut front[n..2 * n], bests, gap_open, gap_extend);
+
+        // the first of equally good cells is kept
+        let mut best_column = (NEG_INF, columns.start);
+        for (j, &best) in columns.clone().zip(bests.iter()) {
+            if best > best_column.0 {
+                best_column = (best, j);
+            }
+        }
+        // alignments end either anywhere in the row or in its last column
+        let last = (bests[n - 1], columns.end - 1);
+        let end = if self.can_end(best_column.1, terminal) {
+            Some(best_column)
+        } else {
+            self.can_end(last.1, terminal).then_some(last)
+        };
+        if let Some((best, j)) = end {
+            if best > self.end.0 {
+                self.end = (best, i, j);
+            }
+        }
+
+        self.best_columns[i] = best_column.1;
+        self.rows[i] = Some(DpRow { columns, cells });
+    }
+
+    /// Whether the best score of the cell is reached other than by the gap
+    /// move `gap`, so that a gap of that kind may be opened from it
+    fn can_open(&self, gap: Move, i: usize, j: usize) -> bool {
+        let order = [Move::Match, Move::Insertion, Move::Deletion];
+        self.best_moves(i, j, Some(gap), order).next().is_some()
+    }
+
+    /// Moves giving the best score at the cell other than `closed`, in the
+    /// given order. None stands for starting the alignment at the cell,
+    /// which is taken first.
+    fn best_moves(
+        &self,
+        i: usize,
+        j: usize,
+        closed: Option<Move>,
+        order: [Move; 3],
+    ) -> impl Iterator<Item = Option<Move>> + '_ {
+        let best = self.best(i, j);
+        let start = (self.start(i, j) == best).then_some(None);
+        start.into_iter().chain(
+            order
+                .into_iter()
+                .filter(move |&mv| Some(mv) != closed && self.get(mv, i, j) == best)
+                .map(Some),
+        )
+    }
+}
+
+/// Rows saved at the start of every block of rows while aligning within a
+/// memory limit. They are the rows later rows still depend on, from which
+/// a block can be filled again when backtracking through it.
+struct Checkpoints {
+    block: usize,
+    saved: Vec<Vec<(usize, DpRow)>>,
 }
 
-// Implementing Ord to define complete comparison
-impl Ord for Candidate {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        (self.score, self.graph_pos, self.seq_pos).cmp(&(
-            other.score,
-            other.graph_pos,
-            other.seq_pos,
-        ))
+impl Checkpoints {
+    fn new(block: usize) -> Self {
+        Self {
+            block,
+            saved: vec![],
+        }
     }
 }
 
-impl PartialOrd for Candidate {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+/// Bytes of sequence profiles kept per alignment
+const PROFILE_BYTES: usize = 64 << 20;
+
+/// Scores of graph tokens against every token of the sequence, column `j`
+/// scoring `seq[j - 1]` and column 0 nothing. Profiles of the tokens met
+/// are kept while they fit in `PROFILE_BYTES`, others are scored for the
+/// row at hand.
+struct Profile<'a, T> {
+    seq: &'a [T],
+    kept: HashMap<&'a T, Vec<i32>>,
+    scratch: Vec<i32>,
+    keep: bool,
+}
+
+impl<'a, T: Token> Profile<'a, T> {
+    fn new(seq: &'a [T], keep: bool) -> Self {
+        Self {
+            seq,
+            kept: HashMap::new(),
+            scratch: vec![],
+            keep,
+        }
+    }
+
+    /// Scores of the token in the columns
+    fn scores<S: Scorer<T> + ?Sized>(
+        &mut self,
+        scorer: &S,
+        token: &'a T,
+        columns: Range<usize>,
+    ) -> &[i32] {
+        let seq = self.seq;
+        let score = |j: usize| {
+            if j > 0 {
+                scorer.score(&seq[j - 1], token)
+            } else {
+                0
+            }
+        };
+        let fits = (self.kept.len() + 1) * (seq.len() + 1) * size_of::<i32>() <= PROFILE_BYTES;
+        if self.keep && fits && !self.kept.contains_key(token) {
+            self.kept
+                .insert(token, (0..seq.len() + 1).map(score).collect());
+        }
+        match self.kept.get(token) {
+            Some(profile) => &profile[columns],
+            None => {
+                self.scratch.clear();
+                self.scratch.extend(columns.map(score));
+                &self.scratch
+            }
+        }
     }
 }
 
-pub struct SeqGraphAlignment {
-    pub seq: Vec<String>,
+/// Rows of the DP matrices. Row 0 is the virtual start node and row `i`
+/// is the `i - 1`th node in topological order.
+struct DpRows {
+    nodes: Vec<NodeIndex>,
+    /// Predecessor rows of each row, later rows first. Nodes without
+    /// predecessors follow the virtual start.
+    predecessors: Vec<Vec<usize>>,
+    /// Whether the node of each row has no successors
+    terminal: Vec<bool>,
+    /// Fewest and most nodes on a path from a source to each node
+    depths: Vec<(usize, usize)>,
+    /// Most nodes on any path
+    longest: usize,
+    /// Fewest and most nodes following each row on a path to a node
+    /// without successors
+    remaining: Vec<(usize, usize)>,
+    /// Last row reading each row, the row itself when nothing reads it
+    last_use: Vec<usize>,
+}
+
+impl DpRows {
+    fn new<T>(graph: &DiGraph<NodeData<T>, EdgeData>, indices: Vec<NodeIndex>) -> Self {
+        let mut row_of_node = vec![0; graph.node_count()];
+        for (position, index) in indices.iter().enumerate() {
+            row_of_node[index.index()] = position + 1;
+        }
+
+        let mut predecessors = vec![vec![]];
+        let mut terminal = vec![false];
+        let mut depths = vec![(0, 0)];
+        let mut last_use = vec![0];
+        for &index in &indices {
+            let mut rows: Vec<usize> = graph
+                .neighbors_directed(index, Direction::Incoming)
+                .map(|prev| row_of_node[prev.index()])
+                .collect();
+            if rows.is_empty() {
+                rows.push(0);
+            }
+            // later predecessors are preferred when backtracking
+            rows.sort_unstable_by(|a, b| b.cmp(a));
+            depths.push((
+                rows.iter().map(|&p| depths[p].0).min().unwrap() + 1,
+                rows.iter().map(|&p| depths[p].1).max().unwrap() + 1,
+            ));
+            for &p in &rows {
+                last_use[p] = predecessors.len();
+            }
+            last_use.push(predecessors.len());
+            predecessors.push(rows);
+            terminal.push(
+                graph
+                    .neighbors_directed(index, Direction::Outgoing)
+                    .next()
+                    .is_none(),
+            );
+        }
+
+        let mut remaining: Vec<_> = terminal
+            .iter()
+            .map(|&terminal| (if terminal { 0 } else { usize::MAX }, 0))
+            .collect();
+        for i in (1..predecessors.len()).rev() {
+            let (fewest, most) = remaining[i];
+            for &p in &predecessors[i] {
+                remaining[p].0 = min(remaining[p].0, fewest + 1);
+                remaining[p].1 = max(remaining[p].1, most + 1);
+            }
+        }
+        // an empty graph has no path
+        remaining[0].0 = min(remaining[0].0, remaining[0].1);
+
+        Self {
+            nodes: indices,
+            predecessors,
+            terminal,
+            longest: depths.iter().map(|depth| depth.1).max().unwrap(),
+            remaining,
+            depths,
+            last_use,
+        }
+    }
+
+    /// Rows per block of checkpointed rows that keeps the full DP for
+    /// `width` columns within `limit` bytes, None when every row fits. When
+    /// nothing fits the block using the least memory is given.
+    fn block_size(&self, width: usize, limit: usize) -> Option<usize> {
+        let height = self.predecessors.len();
+        let row_bytes = 4 * width * size_of::<i32>();
+        if height * row_bytes <= limit {
+            return None;
+        }
+
+        // number of rows before each row still read by it or later rows
+        let mut live = vec![0isize; height + 1];
+        for (i, &last) in self.last_use.iter().enumerate() {
+            live[i + 1] += 1;
+            live[last + 1] -= 1;
+        }
+        let live: Vec<usize> = live
+            .iter()
+            .scan(0, |count, &change| {
+                *count += change;
+                Some(*count as usize)
+            })
+            .collect();
+        let widest = live.iter().copied().max().unwrap_or(0);
+
+        // the saved rows, the live rows while filling and a block of rows
+        // while backtracking
+        let memory = |block: usize| {
+            let saved: usize = (block..height).step_by(block).map(|i| live[i]).sum();
+            (saved + widest + block) * row_bytes
+        };
+        (1..=height)
+            .rev()
+            .find(|&block| memory(block) <= limit)
+            .or_else(|| (1..=height).min_by_key(|&block| memory(block)))
+    }
+
+    /// Node of a row other than the virtual start
+    fn node(&self, i: usize) -> NodeIndex {
+        self.nodes[i - 1]
+    }
+
+    /// Columns of row `i` within the band, the rows before it must be filled
+    fn columns(&self, i: usize, band: Band, dp: &DpMatrices) -> Range<usize> {
+        let (from, to, width) = match band {
+            Band::Full => return 0..dp.width,
+            Band::Fixed(width) => {
+                // the depths of the deepest node span the whole sequence
+                let longest = max(1, self.longest);
+                let (shallowest, deepest) = self.depths[i];
+                (
+                    shallowest * (dp.width - 1) / longest,
+                    (deepest * (dp.width - 1)).div_ceil(longest),
+                    width,
+                )
+            }
+            Band::Adaptive(width) if i > 0 => {
+                let best_columns = self.predecessors[i].iter().map(|&p| dp.best_columns[p]);
+                (
+                    best_columns.clone().min().unwrap() + 1,
+                    best_columns.max().unwrap() + 1,
+                    width,
+                )
+            }
+            Band::Adaptive(width) => (0, 0, width),
+        };
+        let start = min(from.saturating_sub(width), dp.width - 1);
+        start..min(to + width + 1, dp.width)
+    }
+}
+
+/// Score of an alignment under the scorer it was aligned with, and the
+/// number of aligned pairs of each kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AlignmentStats {
+    pub score: i32,
+    /// Aligned tokens the scorer considers a match
+    pub matches: usize,
+    pub mismatches: usize,
+    /// Sequence tokens aligned to no node
+    pub insertions: usize,
+    /// Nodes aligned to no sequence token
+    pub deletions: usize,
+}
+
+impl AlignmentStats {
+    fn new<T: Token, S: Scorer<T> + ?Sized>(
+        score: i32,
+        seq: &[T],
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        scorer: &S,
+        seq_match_positions: &[Option<i32>],
+        graph_match_node_indices: &[Option<NodeIndex>],
+    ) -> Self {
+        let mut stats = Self {
+            score,
+            ..Default::default()
+        };
+        for pair in seq_match_positions.iter().zip(graph_match_node_indices) {
+            match pair {
+                (Some(pos), Some(node)) => {
+                    if scorer.is_match(&seq[*pos as usize], &graph[*node].item) {
+                        stats.matches += 1;
+                    } else {
+                        stats.mismatches += 1;
+                    }
+                }
+                (Some(_), None) => stats.insertions += 1,
+                (None, Some(_)) => stats.deletions += 1,
+                (None, None) => {}
+            }
+        }
+        stats
+    }
+}
+
+pub struct SeqGraphAlignment<T = String> {
+    pub seq: Vec<T>,
     pub label: String,
     // pub graph: DiGraph<NodeData, EdgeData>,
     pub seq_match_positions: Vec<Option<i32>>,
     pub graph_match_node_indices: Vec<Option<NodeIndex>>,
+    stats: AlignmentStats,
 }
 
-impl SeqGraphAlignment {
-    pub fn align_seq_to_graph(
+impl<T: Token> SeqGraphAlignment<T> {
+    /// Align the sequence to the graph, which must be acyclic
+    pub fn align_seq_to_graph<S: Scorer<T> + ?Sized>(
         label: String,
-        seq: Vec<String>,
-        graph: &DiGraph<NodeData, EdgeData>,
-    ) -> Self {
-        let indices = toposort(&graph, None).unwrap();
-        let (
-            mut scores,
-            mut backtrack_scores_seq,
-            mut backtrack_score_graph,
-            node_index_to_matrix_pos,
-            matrix_pos_to_node_index,
-        ) = SeqGraphAlignment::prep_dp_matrix(graph, &indices, &seq);
-
-        // println!("Starting with scores \n {:?}", scores);
-
-        // alignment
-        for (i, &index) in indices.iter().enumerate() {
-            let pbase = graph[index].item.as_str();
-
-            // println!("i={i:?}");
-
-            for (j, sbase) in seq.iter().enumerate() {
-                let mut candidates = vec![Candidate {
-                    score: scores[[i + 1, j]] + GAP,
-                    graph_pos: i + 1,
-                    seq_pos: j,
-                }];
-                // candidates = [(scores[i+1, j] + self._gap, i+1, j, "INS")]
-                let mut prev_positions: Vec<i32> = graph
-                    .edges_directed(index, Direction::Incoming)
-                    .map(|e| node_index_to_matrix_pos[&e.source()] as i32)
-                    .collect();
+        seq: Vec<T>,
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        scorer: &S,
+        options: AlignmentOptions,
+    ) -> Result<Self, GraphError> {
+        Self::align_with_paths(label, seq, graph, None, scorer, options)
+    }
 
-                if prev_positions.is_empty() {
-                    prev_positions = vec![-1];
-                }
+    /// As `align_seq_to_graph`, anchoring along the given paths of the
+    /// sequences of the graph instead of finding them from its edges
+    pub(crate) fn align_with_paths<S: Scorer<T> + ?Sized>(
+        label: String,
+        seq: Vec<T>,
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        paths: Option<&[Vec<NodeIndex>]>,
+        scorer: &S,
+        options: AlignmentOptions,
+    ) -> Result<Self, GraphError> {
+        let order = topological_order(graph)?;
+        let anchored = match (options.mode, options.anchor) {
+            (AlignmentMode::Global, Some(k)) => {
+                align_anchored(&seq, graph, &order, paths, scorer, options, k)
+            }
+            _ => None,
+        };
+        let (score, seq_match_positions, graph_match_node_indices) =
+            anchored.unwrap_or_else(|| Self::align_dp(&seq, graph, order, scorer, options));
+        let stats = AlignmentStats::new(
+            score,
+            &seq,
+            graph,
+            scorer,
+            &seq_match_positions,
+            &graph_match_node_indices,
+        );
 
-                for prev_node_pos in prev_positions {
-                    // candidates += [(scores[predIndex+1, j+1] + self._gap, predIndex+1, j+1, "DEL")]
-                    candidates.push(Candidate {
-                        score: scores[[(prev_node_pos + 1) as usize, j + 1]] + GAP,
-                        graph_pos: (prev_node_pos + 1) as usize,
-                        seq_pos: j + 1,
-                    });
-                    // candidates += [(scores[predIndex+1, j] + self.matchscore(sbase, pbase), predIndex+1, j, "MATCH")]
-                    candidates.push(Candidate {
-                        score: scores[[(prev_node_pos + 1) as usize, j]]
-                            + if sbase == pbase {
-                                MATCHSCORE
-                            } else {
-                                MISMATCHSCORE
-                            },
-                        graph_pos: (prev_node_pos + 1) as usize,
-                        seq_pos: j,
-                    });
-                }
+        Ok(Self {
+            seq,
+            label,
+            seq_match_positions,
+            graph_match_node_indices,
+            stats,
+        })
+    }
 
-                // println!("\tj={}", j);
-                // println!("\tcandidates={:?}", candidates);
-                let chosen_candidate = candidates.iter().max().unwrap();
+    /// Align with the DP over the whole graph, ignoring `options.anchor`.
+    /// `order` lists the nodes in topological order.
+    pub(crate) fn align_dp<S: Scorer<T> + ?Sized>(
+        seq: &[T],
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        order: Vec<NodeIndex>,
+        scorer: &S,
+        options: AlignmentOptions,
+    ) -> Traceback {
+        let rows = DpRows::new(graph, order);
+        let no_reload = |_| unreachable!("every DP row is stored");
 
-                // println!("\t\tchosen candidate={:?}", chosen_candidate);
-                scores[[i + 1, j + 1]] = chosen_candidate.score;
-                backtrack_score_graph[[i + 1, j + 1]] = chosen_candidate.graph_pos as i32;
-                backtrack_scores_seq[[i + 1, j + 1]] = chosen_candidate.seq_pos as i32;
+        // the band is widened while an alignment leaving it could score
+        // better, or no end within it can be reached
+        let mut band = options.band;
+        while band != Band::Full {
+            let dp = Self::fill(graph, &rows, seq, scorer, options, band, None);
+            let best_score = dp.end.0;
+            if best_score > NEG_INF / 2
+                && Self::band_bound(graph, &rows, seq, scorer, &dp) <= best_score as i64
+            {
+                return Self::backtrack(graph, &rows, seq, scorer, dp, no_reload);
             }
+            band = band.widen(seq.len() + 1);
         }
 
-        // println!("scores \n{:?}", scores);
-        // println!("backtrack_score_graph \n{:?}", backtrack_score_graph);
-        // println!("backtrack_scores_seq \n{:?}", backtrack_scores_seq);
+        let block = options
+            .max_memory
+            .and_then(|limit| rows.block_size(seq.len() + 1, limit));
+        let Some(block) = block else {
+            let dp = Self::fill(graph, &rows, seq, scorer, options, Band::Full, None);
+            return Self::backtrack(graph, &rows, seq, scorer, dp, no_reload);
+        };
 
-        let (seq_match_positions, graph_match_node_indices) = SeqGraphAlignment::backtrack(
+        // fill the DP keeping only the rows still needed, then fill each
+        // block again from its checkpoint as the backtrack reaches it
+        let mut checkpoints = Checkpoints::new(block);
+        let mut dp = Self::fill(
             graph,
-            &indices,
-            &scores,
-            &backtrack_scores_seq,
-            &backtrack_score_graph,
-            &matrix_pos_to_node_index,
+            &rows,
+            seq,
+            scorer,
+            options,
+            Band::Full,
+            Some(&mut checkpoints),
         );
+        // every row was dropped once read
+        dp.complete_from = dp.rows.len();
+        let reload = |i: usize| {
+            let height = rows.predecessors.len();
+            let mut dp = DpMatrices::new(options, height, seq.len() + 1);
+            let from = i / block * block;
+            for (row, saved) in std::mem::take(&mut checkpoints.saved[i / block]) {
+                dp.rows[row] = Some(saved);
+            }
+            dp.complete_from = from;
+            Self::fill_rows(
+                graph,
+                &rows,
+                seq,
+                scorer,
+                &mut dp,
+                from..i + 1,
+                Band::Full,
+                None,
+            );
+            dp
+        };
+        Self::backtrack(graph, &rows, seq, scorer, dp, reload)
+    }
 
-        Self {
+    /// Align each of the sequences to the graph in parallel, the graph is
+    /// left as it is. Alignments are in the order of the sequences.
+    pub fn align_batch<S: Scorer<T> + Sync + ?Sized>(
+        sequences: Vec<(String, Vec<T>)>,
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        scorer: &S,
+        options: AlignmentOptions,
+    ) -> Result<Vec<Self>, GraphError>
+    where
+        T: Send + Sync,
+    {
+        // the paths are found once for all of the sequences
+        let paths = options.anchor.map(|_| sequence_paths(graph));
+        Self::align_batch_with_paths(sequences, graph, paths.as_deref(), scorer, options)
+    }
+
+    /// As `align_batch`, anchoring along the given paths of the sequences of
+    /// the graph
+    pub(crate) fn align_batch_with_paths<S: Scorer<T> + Sync + ?Sized>(
+        sequences: Vec<(String, Vec<T>)>,
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        paths: Option<&[Vec<NodeIndex>]>,
+        scorer: &S,
+        options: AlignmentOptions,
+    ) -> Result<Vec<Self>, GraphError>
+    where
+        T: Send + Sync,
+    {
+        sequences
+            .into_par_iter()
+            .map(|(label, seq)| Self::align_with_paths(label, seq, graph, paths, scorer, options))
+            .collect()
+    }
+
+    /// Up to `count` distinct alignments scoring as well as the best one,
+    /// found with the full DP whatever the band, memory limit or anchors.
+    /// The first is the one the tie break picks, the others follow the tie
+    /// break order from each end cell in turn.
+    pub fn co_optimal<S: Scorer<T> + ?Sized>(
+        label: String,
+        seq: Vec<T>,
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        scorer: &S,
+        options: AlignmentOptions,
+        count: usize,
+    ) -> Result<Vec<Self>, GraphError> {
+        let rows = DpRows::new(graph, topological_order(graph)?);
+        let dp = Self::fill(graph, &rows, &seq, scorer, options, Band::Full, None);
+        let best_score = dp.end.0;
+
+        // every cell an alignment may end at with the best score, later ends
+        // are walked back from once the earlier ones are exhausted
+        let mut stack = vec![];
+        for i in (0..rows.predecessors.len()).rev() {
+            for j in (0..dp.width).rev() {
+                if dp.best(i, j) == best_score && dp.can_end(j, rows.terminal[i]) {
+                    let state = (i, j, Pending::Best(None));
+                    let steps = Self::steps(graph, &rows, &seq, scorer, &dp, state);
+                    stack.extend(steps.into_iter().rev().map(|step| (0, step)));
+                }
+            }
+        }
+
+        // depth first over the steps keeping the score, the pairs of the
+        // path walked so far are collected end first
+        let mut pairs = vec![];
+        let mut alignments: Vec<Self> = vec![];
+        while let Some((depth, step)) = stack.pop() {
+            if alignments.len() == count {
+                break;
+            }
+            pairs.truncate(depth);
+            let Step::Pair(pos, node, state) = step else {
+                let (seq_match_positions, graph_match_node_indices): (Vec<_>, Vec<_>) =
+                    pairs.iter().rev().copied().unzip();
+                let found = alignments.iter().any(|aln| {
+                    aln.seq_match_positions == seq_match_positions
+                        && aln.graph_match_node_indices == graph_match_node_indices
+                });
+                if !found {
+                    let stats = AlignmentStats::new(
+                        best_score,
+                        &seq,
+                        graph,
+                        scorer,
+                        &seq_match_positions,
+                        &graph_match_node_indices,
+                    );
+                    alignments.push(Self {
+                        seq: seq.clone(),
+                        label: label.clone(),
+                        seq_match_positions,
+                        graph_match_node_indices,
+                        stats,
+                    });
+                }
+                continue;
+            };
+            pairs.push((pos, node));
+            let steps = Self::steps(graph, &rows, &seq, scorer, &dp, state);
+            stack.extend(steps.into_iter().rev().map(|step| (depth + 1, step)));
+        }
+        Ok(alignments)
+    }
+
+    fn fill<S: Scorer<T> + ?Sized>(
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        rows: &DpRows,
+        seq: &[T],
+        scorer: &S,
+        options: AlignmentOptions,
+        band: Band,
+        checkpoints: Option<&mut Checkpoints>,
+    ) -> DpMatrices {
+        let mut dp = DpMatrices::new(options, rows.predecessors.len(), seq.len() + 1);
+        let all_rows = 0..rows.predecessors.len();
+        Self::fill_rows(
+            graph,
+            rows,
             seq,
-            label,
-            seq_match_positions,
-            graph_match_node_indices,
+            scorer,
+            &mut dp,
+            all_rows,
+            band,
+            checkpoints,
+        );
+        dp
+    }
+
+    /// Fill the given rows, the rows they read must be stored. Deletions,
+    /// matches from the predecessors with their scores from the profile,
+    /// and insertions along the row are computed a whole row at a time with
+    /// vector instructions. With checkpoints the rows still needed are
+    /// saved at the start of each block and rows are dropped once read by
+    /// all of their successors.
+    #[allow(clippy::too_many_arguments)]
+    fn fill_rows<S: Scorer<T> + ?Sized>(
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        rows: &DpRows,
+        seq: &[T],
+        scorer: &S,
+        dp: &mut DpMatrices,
+        fill: Range<usize>,
+        band: Band,
+        mut checkpoints: Option<&mut Checkpoints>,
+    ) {
+        let gap_open = scorer.gap_open() + scorer.gap_extend();
+        let gap_extend = scorer.gap_extend();
+        let mut deletion = vec![];
+        let mut matching = vec![];
+        // banded rows only score their columns
+        let mut profile = Profile::new(seq, band == Band::Full);
+
+        for i in fill {
+            if let Some(checkpoints) = checkpoints.as_deref_mut() {
+                if i % checkpoints.block == 0 {
+                    let saved = dp
+                        .rows
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(row, saved)| Some((row, saved.clone()?)))
+                        .collect();
+                    checkpoints.saved.push(saved);
+                }
+            }
+
+            let columns = rows.columns(i, band, dp);
+            deletion.clear();
+            deletion.resize(columns.len(), NEG_INF);
+            matching.clear();
+            matching.resize(columns.len(), NEG_INF);
+
+            // leading sequence tokens before the graph starts are insertions
+            // along the virtual start row
+            if i > 0 {
+                for &prev_pos in &rows.predecessors[i] {
+                    let prev = dp.row(prev_pos);
+
+                    // deletions consume this node and can follow any predecessor
+                    if let Some(shared) = overlap(&columns, &prev.columns) {
+                        let from = shared.start - prev.columns.start;
+                        dp.simd.max_deletion(
+                            &mut deletion[shared.start - columns.start..shared.end - columns.start],
+                            &prev.bests()[from..],
+                            &prev.deletions()[from..],
+                            gap_open,
+                            gap_extend,
+                        );
+                    }
+
+                    // matches come from the previous column of a predecessor
+                    let shifted_columns = prev.columns.start + 1..prev.columns.end + 1;
+                    if let Some(shifted) = overlap(&columns, &shifted_columns) {
+                        dp.simd.max_assign(
+                            &mut matching
+                                [shifted.start - columns.start..shifted.end - columns.start],
+                            &prev.bests()[shifted.start - shifted_columns.start..],
+                        );
+                    }
+                }
+
+                let pbase = &graph[rows.node(i)].item;
+                let scores = profile.scores(scorer, pbase, columns.clone());
+                dp.simd.add_assign(&mut matching, scores);
+            }
+
+            let terminal = rows.terminal[i];
+            dp.push_row(
+                i, columns, &deletion, &matching, gap_open, gap_extend, terminal,
+            );
+
+            if checkpoints.is_some() {
+                for &prev_pos in rows.predecessors[i].iter().chain([&i]) {
+                    if rows.last_use[prev_pos] == i {
+                        dp.rows[prev_pos] = None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Every way of walking back from the state that keeps the score, in the
+    /// order the tie break takes them. The rows of the cell and of its
+    /// predecessors must be stored.
+    fn steps<S: Scorer<T> + ?Sized>(
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        rows: &DpRows,
+        seq: &[T],
+        scorer: &S,
+        dp: &DpMatrices,
+        (i, j, pending): State,
+    ) -> Vec<Step> {
+        let gap_open = scorer.gap_open() + scorer.gap_extend();
+        let gap_extend = scorer.gap_extend();
+        let gaps_first = [Move::Insertion, Move::Deletion, Move::Match];
+        let order = match dp.tie_break {
+            TieBreak::RightGaps => gaps_first,
+            TieBreak::LeftGaps => [Move::Match, Move::Deletion, Move::Insertion],
+            TieBreak::Matches
+                if i > 0 && j > 0 && scorer.is_match(&seq[j - 1], &graph[rows.node(i)].item) =>
+            {
+                [Move::Match, Move::Insertion, Move::Deletion]
+            }
+            TieBreak::Matches => gaps_first,
+        };
+        // left gaps leave a gap as soon as the score allows, walking back,
+        // to take matches first
+        let extend_first = dp.tie_break != TieBreak::LeftGaps;
+        let moves: Vec<_> = match pending {
+            Pending::Best(closed) => dp.best_moves(i, j, closed, order).collect(),
+            Pending::Extend(mv) => vec![Some(mv)],
+        };
+
+        let mut steps = vec![];
+        for mv in moves {
+            let Some(mv) = mv else {
+                steps.push(Step::Start);
+                continue;
+            };
+            let score = dp.get(mv, i, j);
+            match mv {
+                Move::Match => {
+                    let pbase = &graph[rows.node(i)].item;
+                    let match_score = scorer.score(&seq[j - 1], pbase);
+                    for &prev_pos in &rows.predecessors[i] {
+                        if dp.best(prev_pos, j - 1) + match_score == score {
+                            steps.push(Step::Pair(
+                                Some(j as i32 - 1),
+                                Some(rows.node(i)),
+                                (prev_pos, j - 1, Pending::Best(None)),
+                            ));
+                        }
+                    }
+                }
+                Move::Insertion => {
+                    let mut gaps = [
+                        (
+                            dp.get(Move::Insertion, i, j - 1) + gap_extend,
+                            Pending::Extend(mv),
+                        ),
+                        (dp.best(i, j - 1) + gap_open, Pending::Best(Some(mv))),
+                    ];
+                    if !extend_first {
+                        gaps.reverse();
+                    }
+                    for (gap_score, pending) in gaps {
+                        let opens = pending == Pending::Best(Some(mv));
+                        if gap_score == score && (!opens || dp.can_open(mv, i, j - 1)) {
+                            steps.push(Step::Pair(Some(j as i32 - 1), None, (i, j - 1, pending)));
+                        }
+                    }
+                }
+                Move::Deletion => {
+                    for &prev_pos in &rows.predecessors[i] {
+                        let mut gaps = [
+                            (
+                                dp.get(Move::Deletion, prev_pos, j) + gap_extend,
+                                Pending::Extend(mv),
+                            ),
+                            (dp.best(prev_pos, j) + gap_open, Pending::Best(Some(mv))),
+                        ];
+                        if !extend_first {
+                            gaps.reverse();
+                        }
+                        for (gap_score, pending) in gaps {
+                            let opens = pending == Pending::Best(Some(mv));
+                            if gap_score == score && (!opens || dp.can_open(mv, prev_pos, j)) {
+                                steps.push(Step::Pair(
+                                    None,
+                                    Some(rows.node(i)),
+                                    (prev_pos, j, pending),
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        steps
+    }
+
+    /// Upper bound on the score of any alignment passing through a cell
+    /// outside of the band, so that the banded alignment is the best one
+    /// when it scores at least as well. Such an alignment either starts
+    /// outside of the band or leaves it in one move from a cell within it,
+    /// whose banded score bounds the alignment up to there. After that every
+    /// pair scores at most the best score of any two tokens aligned and
+    /// every gap position at most `gap_extend`.
+    fn band_bound<S: Scorer<T> + ?Sized>(
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        rows: &DpRows,
+        seq: &[T],
+        scorer: &S,
+        dp: &DpMatrices,
+    ) -> i64 {
+        let gap_extend = scorer.gap_extend() as i64;
+        if scorer.gap_open() > 0 || gap_extend > 0 {
+            // gaps do not lower the score, nothing bounds it
+            return i64::MAX;
+        }
+        let seq_tokens: HashSet<&T> = seq.iter().collect();
+        let node_tokens: HashSet<&T> = rows.nodes.iter().map(|&node| &graph[node].item).collect();
+        let best_pair = seq_tokens
+            .iter()
+            .flat_map(|a| node_tokens.iter().map(|b| scorer.score(a, b)))
+            .max()
+            .unwrap_or(0)
+            .max(0) as i64;
+
+        // best score of aligning between `a` sequence tokens and `l` nodes
+        let pairs = |(a_from, a_to): (usize, usize), (l_from, l_to): (usize, usize)| {
+            if a_to < l_from {
+                best_pair * a_to as i64 + gap_extend * (l_from - a_to) as i64
+            } else if l_to < a_from {
+                best_pair * l_to as i64 + gap_extend * (a_from - l_to) as i64
+            } else {
+                best_pair * min(a_to, l_to) as i64
+            }
+        };
+        // best score of the rest of an alignment from the cell to any end
+        let last = dp.width - 1;
+        let rest = |i: usize, j: usize| {
+            let (fewest, most) = rows.remaining[i];
+            let to_last = (last - j, last - j);
+            let to_any = (0, last - j);
+            match dp.mode {
+                AlignmentMode::Global => pairs(to_last, (fewest, most)),
+                AlignmentMode::Local => pairs(to_any, (0, most)),
+                AlignmentMode::Fitting => pairs(to_last, (0, most)),
+                AlignmentMode::Containment => pairs(to_any, (fewest, most)),
+                AlignmentMode::Overlap => {
+                    max(pairs(to_any, (fewest, most)), pairs(to_last, (0, most)))
+                }
+            }
+        };
+        let reached = |score: i32| (score > NEG_INF / 2).then_some(score as i64);
+
+        let mut bound = i64::MIN;
+        for i in 0..rows.predecessors.len() {
+            let row = dp.row(i);
+            let columns = &row.columns;
+            let outside = |j: usize| !columns.contains(&j);
+
+            // alignments starting outside of the band, the rest of an
+            // alignment scores no worse from an earlier column in local mode
+            let mut starts = vec![];
+            if i == 0 {
+                starts.extend(columns.end..dp.width);
+            } else if dp.mode == AlignmentMode::Local {
+                starts.extend([0, columns.end].into_iter().filter(|&j| j < dp.width));
+            } else {
+                starts.push(0);
+            }
+            for j in starts {
+                if outside(j) && dp.start(i, j) == 0 {
+                    bound = max(bound, rest(i, j));
+                }
+            }
+
+            // insertions leave the band to the right
+            if columns.end < dp.width {
+                if let Some(score) = reached(row.bests()[columns.len() - 1]) {
+                    bound = max(bound, score + gap_extend + rest(i, columns.end));
+                }
+            }
+            if i == 0 {
+                continue;
+            }
+
+            // deletions and matches leave it from the predecessors
+            let pbase = &graph[rows.node(i)].item;
+            for &prev_pos in &rows.predecessors[i] {
+                let prev = dp.row(prev_pos);
+                for (j, &score) in prev.columns.clone().zip(prev.bests()) {
+                    let Some(score) = reached(score) else {
+                        continue;
+                    };
+                    if outside(j) {
+                        bound = max(bound, score + gap_extend + rest(i, j));
+                    }
+                    if j + 1 < dp.width && outside(j + 1) {
+                        let match_score = scorer.score(&seq[j], pbase) as i64;
+                        bound = max(bound, score + match_score + rest(i, j + 1));
+                    }
+                }
+            }
+        }
+        bound
+    }
+
+    /// Walk back from the best end cell taking the first step the tie break
+    /// gives, `reload` gives the rows needed to continue from a row whose
+    /// predecessors are not stored
+    fn backtrack<S: Scorer<T> + ?Sized>(
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        rows: &DpRows,
+        seq: &[T],
+        scorer: &S,
+        mut dp: DpMatrices,
+        mut reload: impl FnMut(usize) -> DpMatrices,
+    ) -> Traceback {
+        // end at the best scoring cell the mode allows an alignment to end at
+        let (best_score, i, j) = dp.end;
+
+        let mut graph_match_node_indices = vec![];
+        let mut seq_match_positions = vec![];
+        let mut state = (i, j, Pending::Best(None));
+
+        // walk back until reaching the cell the alignment starts at, the
+        // moves are collected end first
+        loop {
+            let (i, _, _) = state;
+            if i < dp.complete_from {
+                // free the rows of the block behind before filling the next
+                drop(dp);
+                dp = reload(i);
+            }
+            let step = Self::steps(graph, rows, seq, scorer, &dp, state)
+                .into_iter()
+                .next()
+                .unwrap();
+            let Step::Pair(pos, node, next) = step else {
+                break;
+            };
+            seq_match_positions.push(pos);
+            graph_match_node_indices.push(node);
+            state = next;
         }
+
+        seq_match_positions.reverse();
+        graph_match_node_indices.reverse();
+        (best_score, seq_match_positions, graph_match_node_indices)
+    }
+}
+
+impl<T> SeqGraphAlignment<T> {
+    pub fn stats(&self) -> AlignmentStats {
+        self.stats
+    }
+
+    /// Score of the alignment under the scorer it was aligned with
+    pub fn score(&self) -> i32 {
+        self.stats.score
+    }
+
+    pub fn matches(&self) -> usize {
+        self.stats.matches
+    }
+
+    pub fn mismatches(&self) -> usize {
+        self.stats.mismatches
+    }
+
+    pub fn insertions(&self) -> usize {
+        self.stats.insertions
     }
 
-    pub fn get_string(
+    pub fn deletions(&self) -> usize {
+        self.stats.deletions
+    }
+}
+
+impl<T: Token> SeqGraphAlignment<T> {
+    /// Render the aligned sequence, match markers and graph tokens, tokens
+    /// are rendered with `render`
+    pub fn get_string<F: Fn(&T) -> String>(
         &self,
-        graph: &DiGraph<NodeData, EdgeData>,
+        graph: &DiGraph<NodeData<T>, EdgeData>,
+        render: F,
     ) -> (usize, Vec<String>, Vec<String>, Vec<String>) {
         let mut width = 0;
         let s1: Vec<String> = self
@@ -162,7 +1277,7 @@ impl SeqGraphAlignment {
             .iter()
             .map(|item| {
                 if let Some(pos) = item {
-                    let s = self.seq[*pos as usize].to_string();
+                    let s = render(&self.seq[*pos as usize]);
                     width = max(width, s.len());
                     s
                 } else {
@@ -176,7 +1291,7 @@ impl SeqGraphAlignment {
             .iter()
             .map(|item| {
                 if let Some(pos) = item {
-                    let s = graph[*pos].item.clone();
+                    let s = render(&graph[*pos].item);
                     width = max(width, s.len());
                     s
                 } else {
@@ -199,172 +1314,429 @@ impl SeqGraphAlignment {
 
         (width, s1, m, s2)
     }
+}
+
+#[cfg(test)]
+mod alignment_tests {
+    use crate::{
+        graph::POAGraph,
+        scoring::{Scorer, Scoring},
+        simd::Simd,
+        test_utils::{align, graph_of, to_seq, Rng, MODES},
+    };
+
+    use super::{AlignmentMode, AlignmentOptions, Band, SeqGraphAlignment, TieBreak};
+
+    #[test]
+    fn align_seq_test() {
+        // "AMTGXT"
+        let graph = graph_of(&[("seq_1", "MTGXT")]);
+        let sg_aln = SeqGraphAlignment::align_seq_to_graph(
+            "seq_2".to_string(),
+            to_seq("ATGXT"),
+            &graph.graph,
+            &Scoring::default(),
+            AlignmentMode::Global.into(),
+        )
+        .unwrap();
+        let (_, f1, m, f2) = sg_aln.get_string(&graph.graph, |s| s.clone());
+        println!("{f1:?}");
+        println!("{m:?}");
+        println!("{f2:?}");
+        // four matches and a mismatch
+        assert_eq!(sg_aln.score(), 3);
+        assert_eq!(sg_aln.matches(), 4);
+        assert_eq!(sg_aln.mismatches(), 1);
+        assert_eq!(sg_aln.insertions() + sg_aln.deletions(), 0);
+    }
+
+    #[test]
+    fn batch_alignment_test() {
+        let graph = graph_of(&[("seq_1", "ACGTACGTACGT"), ("seq_2", "ACGTTACGTACG")]);
 
-    fn backtrack(
-        graph: &DiGraph<NodeData, EdgeData>,
-        indices: &[NodeIndex],
-        scores: &Matrix,
-        backtrack_scores_seq: &Matrix,
-        backtrack_score_graph: &Matrix,
-        matrix_pos_to_node_index: &HashMap<usize, NodeIndex>,
-    ) -> (Vec<Option<i32>>, Vec<Option<NodeIndex>>) {
-        let shape = scores.shape();
-        let mut besti = shape[0] as i32 - 1;
-        let mut bestj = shape[1] as i32 - 1;
-
-        // global alignment scenario
-        if true {
-            let terminal_indices: Vec<i32> = indices
+        let queries: Vec<(String, Vec<String>)> = ["ACGTACGT", "TTACGTTACGTACGAA", "GGGG", ""]
+            .iter()
+            .enumerate()
+            .map(|(i, seq)| (format!("query_{}", i), to_seq(seq)))
+            .collect();
+        let batch = SeqGraphAlignment::align_batch(
+            queries.clone(),
+            &graph.graph,
+            &Scoring::default(),
+            AlignmentMode::Local.into(),
+        )
+        .unwrap();
+
+        assert_eq!(batch.len(), queries.len());
+        for (aln, (label, seq)) in batch.iter().zip(queries) {
+            let single = SeqGraphAlignment::align_seq_to_graph(
+                label.clone(),
+                seq,
+                &graph.graph,
+                &Scoring::default(),
+                AlignmentMode::Local.into(),
+            )
+            .unwrap();
+            assert_eq!(aln.label, label);
+            assert_eq!(aln.stats(), single.stats());
+            assert_eq!(aln.seq_match_positions, single.seq_match_positions);
+            assert_eq!(
+                aln.graph_match_node_indices,
+                single.graph_match_node_indices
+            );
+        }
+        assert_eq!(batch[0].score(), 8);
+        assert_eq!(batch[3].score(), 0);
+    }
+
+    #[test]
+    fn scoring_test() {
+        let graph = graph_of(&[("seq_1", "ACGT")]);
+
+        // a mismatch is cheaper than two gaps
+        let sg_aln = align(&graph, "AGGT", AlignmentMode::Global);
+        assert_eq!(sg_aln.seq_match_positions.len(), 4);
+
+        // two gaps are cheaper than a mismatch
+        let sg_aln = graph
+            .align(
+                "seq_2".to_string(),
+                to_seq("AGGT"),
+                &Scoring::new(1, -5, 0, -1),
+                AlignmentMode::Global.into(),
+            )
+            .unwrap();
+        assert_eq!(sg_aln.seq_match_positions.len(), 5);
+    }
+
+    #[test]
+    fn affine_gap_test() {
+        let graph = graph_of(&[("seq_1", "AAGGTT")]);
+        let gap_runs = |scoring: &Scoring| {
+            let sg_aln = graph
+                .align(
+                    "seq_2".to_string(),
+                    to_seq("AGT"),
+                    scoring,
+                    AlignmentMode::Global.into(),
+                )
+                .unwrap();
+            assert_eq!(sg_aln.seq_match_positions.len(), 6);
+            sg_aln
+                .seq_match_positions
                 .iter()
                 .enumerate()
-                .filter_map(|(pos, index)| {
-                    if graph.edges_directed(*index, Direction::Outgoing).count() == 0 {
-                        Some(pos as i32)
-                    } else {
-                        None
-                    }
+                .filter(|(pos, item)| {
+                    item.is_none() && (*pos == 0 || sg_aln.seq_match_positions[pos - 1].is_some())
                 })
-                .collect();
-            besti = terminal_indices[0] + 1;
-            let mut best_score = scores[[besti as usize, bestj as usize]];
-
-            for &i in &terminal_indices[1..] {
-                let score = scores[[(i + 1) as usize, bestj as usize]];
-                if score > best_score {
-                    best_score = score;
-                    besti = i + 1;
+                .count()
+        };
+
+        // linear gaps are scattered around the matches
+        assert!(gap_runs(&Scoring::default()) > 1);
+        // an expensive gap open keeps the deletions together
+        assert_eq!(gap_runs(&Scoring::new(1, -1, -10, -1)), 1);
+    }
+
+    #[test]
+    fn local_alignment_test() {
+        let graph = graph_of(&[("seq_1", "XXACGTYY")]);
+        let sg_aln = align(&graph, "ZZACGTZZ", AlignmentMode::Local);
+        // only the shared core is aligned
+        assert_eq!(
+            sg_aln.seq_match_positions,
+            vec![Some(2), Some(3), Some(4), Some(5)]
+        );
+        let (_, f1, _, f2) = sg_aln.get_string(&graph.graph, |s| s.clone());
+        assert_eq!(f1, f2);
+
+        // nothing scores above zero
+        let sg_aln = align(&graph, "ZZZ", AlignmentMode::Local);
+        assert!(sg_aln.seq_match_positions.is_empty());
+    }
+
+    #[test]
+    fn semi_global_alignment_test() {
+        let aligned_nodes = |sg_aln: &SeqGraphAlignment| {
+            sg_aln
+                .graph_match_node_indices
+                .iter()
+                .filter(|index| index.is_some())
+                .count()
+        };
+
+        // a fragment sits inside the graph without paying for the flanks
+        let graph = graph_of(&[("seq_1", "XXXACGTYYY")]);
+        let sg_aln = align(&graph, "ACGT", AlignmentMode::Fitting);
+        assert_eq!(sg_aln.seq_match_positions.len(), 4);
+        assert_eq!(aligned_nodes(&sg_aln), 4);
+
+        // the graph sits inside the sequence, flanking tokens are left out
+        let graph = graph_of(&[("seq_1", "ACGT")]);
+        let sg_aln = align(&graph, "XXXACGTYYY", AlignmentMode::Containment);
+        assert_eq!(
+            sg_aln.seq_match_positions,
+            vec![Some(3), Some(4), Some(5), Some(6)]
+        );
+
+        // the end of the graph overlaps the start of the sequence
+        let graph = graph_of(&[("seq_1", "XXXACGT")]);
+        let sg_aln = align(&graph, "ACGTYYY", AlignmentMode::Overlap);
+        assert_eq!(
+            sg_aln.seq_match_positions,
+            vec![Some(0), Some(1), Some(2), Some(3)]
+        );
+        assert_eq!(aligned_nodes(&sg_aln), 4);
+    }
+
+    #[test]
+    fn banded_alignment_test() {
+        let score = |graph: &POAGraph, seq: &[String], mode: AlignmentMode, band: Band| {
+            let options = AlignmentOptions {
+                mode,
+                band,
+                ..Default::default()
+            };
+            let aln = graph
+                .align(
+                    "query".to_string(),
+                    seq.to_vec(),
+                    &Scoring::default(),
+                    options,
+                )
+                .unwrap();
+            aln.score()
+        };
+
+        // the best alignment lies outside of the band but has to be found
+        let graph = graph_of(&[("seq_1", "AA")]);
+        let global = AlignmentMode::Global;
+        assert_eq!(score(&graph, &to_seq("BBBAA"), global, Band::Fixed(2)), -4);
+
+        // random graphs and queries, the banded alignment scores as well as
+        // the full DP in every mode
+        let mut rng = Rng::new(0x2545_f491_4f6c_dd1d);
+        for _ in 0..60 {
+            let graph = rng.graph(5, 12);
+            let query = rng.seq(12);
+            for mode in MODES {
+                let full = score(&graph, &query, mode, Band::Full);
+                for width in 0..4 {
+                    for band in [Band::Fixed(width), Band::Adaptive(width)] {
+                        assert_eq!(score(&graph, &query, mode, band), full, "{:?}", band);
+                    }
                 }
             }
         }
+    }
 
-        let mut graph_match_node_indices = vec![];
-        let mut seq_match_positions = vec![];
-        let mut nexti;
-        let mut nextj;
-        let mut current_seq_pos;
-        let mut current_node_index;
-        let is_global = true;
-
-        while (is_global || scores[[besti as usize, bestj as usize]] > 0)
-            && (besti != 0 || bestj != 0)
-        {
-            nexti = backtrack_score_graph[[besti as usize, bestj as usize]];
-            nextj = backtrack_scores_seq[[besti as usize, bestj as usize]];
-            current_seq_pos = bestj - 1;
-
-            current_node_index = if besti > 0 {
-                matrix_pos_to_node_index[&((besti - 1) as usize)]
-            } else {
-                NodeIndex::from(u32::MAX)
-            };
+    #[test]
+    fn simd_alignment_test() {
+        let mut rng = Rng::new(0x9e37_79b9_7f4a_7c15);
+        // linear and affine gap penalties
+        let scorers = [
+            Scoring::default(),
+            Scoring::new(2, -3, -4, -1),
+            Scoring::new(1, -1, -1, -1),
+        ];
 
-            seq_match_positions.insert(
-                0,
-                if nextj != bestj {
-                    Some(current_seq_pos)
-                } else {
-                    None
-                },
-            );
-            graph_match_node_indices.insert(
-                0,
-                if nexti != besti {
-                    Some(current_node_index)
-                } else {
-                    None
-                },
-            );
-            besti = nexti;
-            bestj = nextj;
+        // every instruction set gives the same scores and tracebacks
+        for _ in 0..20 {
+            let graph = rng.graph(5, 40);
+            let query = rng.seq(40);
+            for (scorer, mode, band) in scorers.iter().flat_map(|scorer| {
+                MODES.iter().flat_map(move |&mode| {
+                    [Band::Full, Band::Adaptive(3)].map(|band| (scorer, mode, band))
+                })
+            }) {
+                let options = AlignmentOptions {
+                    mode,
+                    band,
+                    ..Default::default()
+                };
+                let tracebacks: Vec<_> = Simd::supported()
+                    .into_iter()
+                    .map(|simd| {
+                        Simd::force(Some(simd));
+                        let aln = graph
+                            .align("query".to_string(), query.clone(), scorer, options)
+                            .unwrap();
+                        (
+                            aln.score(),
+                            aln.seq_match_positions,
+                            aln.graph_match_node_indices,
+                        )
+                    })
+                    .collect();
+                Simd::force(None);
+                assert!(tracebacks
+                    .iter()
+                    .all(|traceback| traceback == &tracebacks[0]));
+            }
         }
+    }
+
+    #[test]
+    fn memory_bounded_alignment_test() {
+        let graph = graph_of(&[
+            ("seq_1", "ACGTACGTACGTACGT"),
+            ("seq_2", "ACGTACCGTACGTTACGT"),
+            ("seq_3", "AGTACGTAACGTACG"),
+        ]);
+        let bounded = |mode: AlignmentMode, max_memory: Option<usize>| {
+            let options = AlignmentOptions {
+                mode,
+                max_memory,
+                ..Default::default()
+            };
+            align(&graph, "TTACGTACGGTACGTACGAA", options)
+        };
 
-        (seq_match_positions, graph_match_node_indices)
+        for mode in [AlignmentMode::Global, AlignmentMode::Local] {
+            let full = bounded(mode, None);
+            // from a checkpoint every few rows down to a single row
+            for max_memory in [2000, 500, 0] {
+                let bounded = bounded(mode, Some(max_memory));
+                assert_eq!(bounded.seq_match_positions, full.seq_match_positions);
+                assert_eq!(
+                    bounded.graph_match_node_indices,
+                    full.graph_match_node_indices
+                );
+            }
+        }
     }
 
-    fn prep_dp_matrix(
-        graph: &DiGraph<NodeData, EdgeData>,
-        indices: &[NodeIndex],
-        seq: &[String],
-    ) -> (
-        Matrix,
-        Matrix,
-        Matrix,
-        HashMap<NodeIndex, usize>,
-        HashMap<usize, NodeIndex>,
-    ) {
-        let l1 = graph.node_count();
-        let l2 = seq.len();
-        let mut scores = Array2::<i32>::zeros((l1 + 1, l2 + 1));
-        let mut node_index_to_matrix_pos: HashMap<NodeIndex, usize> = HashMap::new();
-        let mut matrix_pos_to_node_index: HashMap<usize, NodeIndex> = HashMap::new();
-        let backtrack_scores_seq = Array2::<i32>::zeros((l1 + 1, l2 + 1));
-        let backtrack_score_graph = Array2::<i32>::zeros((l1 + 1, l2 + 1));
-
-        for (position, &node_index) in indices.iter().enumerate() {
-            node_index_to_matrix_pos.insert(node_index, position);
-            matrix_pos_to_node_index.insert(position, node_index);
-        }
-
-        // if global alignment
-        if true {
-            for i in 0..l2 + 1 {
-                scores[[0, i]] = (i as i32) * GAP; // mul by gap later
-            }
-
-            for (matrix_pos, &node_index) in indices.iter().enumerate() {
-                let prev_node_indices = graph.edges_directed(node_index, Direction::Incoming);
-                let mut best_score = 0;
-                for edge in prev_node_indices {
-                    // if score is zero and there are incoming edges (we're in this loop), it is not possible
-                    if best_score == 0 {
-                        best_score = scores[[node_index_to_matrix_pos[&edge.source()] + 1, 0]];
-                    }
-                    best_score = max(
-                        best_score,
-                        scores[[node_index_to_matrix_pos[&edge.source()] + 1, 0]],
-                    );
+    #[test]
+    fn custom_scorer_test() {
+        // kilometre posts within one of each other are the same place
+        struct NearbyPosts;
+
+        impl Scorer<i32> for NearbyPosts {
+            fn score(&self, a: &i32, b: &i32) -> i32 {
+                if (a - b).abs() <= 1 {
+                    2
+                } else {
+                    -2
                 }
-                scores[[matrix_pos + 1, 0]] = best_score + GAP;
+            }
+
+            fn gap_open(&self) -> i32 {
+                0
+            }
+
+            fn gap_extend(&self) -> i32 {
+                -1
             }
         }
-        (
-            scores,
-            backtrack_scores_seq,
-            backtrack_score_graph,
-            node_index_to_matrix_pos,
-            matrix_pos_to_node_index,
-        )
+
+        let graph = POAGraph::new("seq_1".to_string(), vec![10, 20, 30]).unwrap();
+        let sg_aln = graph
+            .align(
+                "seq_2".to_string(),
+                vec![11, 19, 31],
+                &NearbyPosts,
+                AlignmentMode::Global.into(),
+            )
+            .unwrap();
+        assert_eq!(sg_aln.seq_match_positions, vec![Some(0), Some(1), Some(2)]);
+        assert!(sg_aln.graph_match_node_indices.iter().all(|i| i.is_some()));
     }
-}
 
-#[cfg(test)]
-mod alignment_tests {
-    use crate::graph::POAGraph;
+    #[test]
+    fn tie_break_test() {
+        let align = |graph: &POAGraph, seq: &str, scoring: &Scoring, tie_break: TieBreak| {
+            let options = AlignmentOptions {
+                tie_break,
+                ..Default::default()
+            };
+            graph
+                .align("seq_2".to_string(), to_seq(seq), scoring, options)
+                .unwrap()
+        };
+
+        // either C may be deleted
+        let graph = graph_of(&[("seq_1", "ACCT")]);
+        let right = align(&graph, "ACT", &Scoring::default(), TieBreak::RightGaps);
+        assert_eq!(
+            right.seq_match_positions,
+            vec![Some(0), Some(1), None, Some(2)]
+        );
+        let left = align(&graph, "ACT", &Scoring::default(), TieBreak::LeftGaps);
+        assert_eq!(
+            left.seq_match_positions,
+            vec![Some(0), None, Some(1), Some(2)]
+        );
 
-    use super::SeqGraphAlignment;
+        // a mismatch scores as much as a pair of gaps
+        let graph = graph_of(&[("seq_1", "AGT")]);
+        let scoring = Scoring::new(1, -2, 0, -1);
+        let left = align(&graph, "ACT", &scoring, TieBreak::LeftGaps);
+        assert_eq!((left.mismatches(), left.insertions()), (1, 0));
+        let matches = align(&graph, "ACT", &scoring, TieBreak::Matches);
+        assert_eq!((matches.mismatches(), matches.insertions()), (0, 1));
+        assert_eq!(matches.score(), left.score());
+    }
 
     #[test]
-    fn align_seq_test() {
-        let seq1 = vec![
-            // "A".to_string(),
-            "M".to_string(),
-            "T".to_string(),
-            "G".to_string(),
-            "X".to_string(),
-            "T".to_string(),
-        ];
-        let seq2 = vec![
-            "A".to_string(),
-            "T".to_string(),
-            "G".to_string(),
-            "X".to_string(),
-            "T".to_string(),
-        ];
-        let graph = POAGraph::new("seq_1".to_string(), seq1);
-        let sg_aln = SeqGraphAlignment::align_seq_to_graph("seq_2".to_string(), seq2, &graph.graph);
-        let (_, f1, m, f2) = sg_aln.get_string(&graph.graph);
-        println!("{f1:?}");
-        println!("{m:?}");
-        println!("{f2:?}");
+    fn co_optimal_test() {
+        let graph = graph_of(&[("seq_1", "ACCT")]);
+        let scoring = Scoring::default();
+        let options = AlignmentOptions::default();
+        let best = align(&graph, "ACT", options);
+        let all = SeqGraphAlignment::co_optimal(
+            "seq_2".to_string(),
+            to_seq("ACT"),
+            &graph.graph,
+            &scoring,
+            options,
+            10,
+        )
+        .unwrap();
+
+        // deleting either C, the tie break pick first
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].seq_match_positions, best.seq_match_positions);
+        assert_eq!(
+            all[0].graph_match_node_indices,
+            best.graph_match_node_indices
+        );
+        assert_ne!(all[1].seq_match_positions, best.seq_match_positions);
+        assert!(all.iter().all(|aln| aln.score() == best.score()));
+
+        let first = SeqGraphAlignment::co_optimal(
+            "seq_2".to_string(),
+            to_seq("ACT"),
+            &graph.graph,
+            &scoring,
+            options,
+            1,
+        )
+        .unwrap();
+        assert_eq!(first.len(), 1);
+    }
+
+    /// Timing of building a graph of tens of thousands of nodes from 20
+    /// copies of a sequence of 10k tokens with 5% substitutions, deletions
+    /// and insertions each, run with
+    /// `cargo test --release -- --ignored --nocapture large_graph_timing_test`
+    #[test]
+    #[ignore]
+    fn large_graph_timing_test() {
+        let mut rng = Rng::new(0x2545_f491_4f6c_dd1d);
+        let template: Vec<String> = (0..10_000).map(|_| rng.base()).collect();
+        let records = (0..20)
+            .map(|k| (format!("seq_{}", k), rng.mutate(&template, 20)))
+            .collect();
+
+        let start = std::time::Instant::now();
+        let graph =
+            POAGraph::from_records(records, &Scoring::default(), AlignmentOptions::default())
+                .unwrap();
+        println!(
+            "built a graph of {} nodes in {:?}",
+            graph.graph.node_count(),
+            start.elapsed()
+        );
+        assert!(graph.graph.node_count() > 20_000);
     }
 }
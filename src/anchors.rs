@@ -0,0 +1,617 @@
+use crate::{
+    alignment::{AlignmentOptions, SeqGraphAlignment, Traceback},
+    graph::{EdgeData, NodeData, Token},
+    scoring::Scorer,
+};
+use petgraph::{
+    graph::{DiGraph, NodeIndex},
+    visit::EdgeRef,
+    Direction,
+};
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    hash::{BuildHasher, RandomState},
+};
+
+/// Multiplier of the rolling hash of k-mers
+const BASE: u64 = 0x100000001b3;
+
+/// Run of the sequence from `start` matching a path of graph nodes exactly
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Anchor {
+    start: usize,
+    nodes: Vec<NodeIndex>,
+}
+
+impl Anchor {
+    fn end(&self) -> usize {
+        self.start + self.nodes.len()
+    }
+}
+
+/// Nodes each sequence of the graph passes through, in order, found from
+/// the sequences of its edges. Sequences of a single token have no edges,
+/// only those whose node has no edges at all are found. `POAGraph` keeps
+/// the paths of its sequences as they are added.
+pub(crate) fn sequence_paths<T>(graph: &DiGraph<NodeData<T>, EdgeData>) -> Vec<Vec<NodeIndex>> {
+    let mut next: Vec<HashMap<NodeIndex, NodeIndex>> = vec![];
+    let mut entered: Vec<HashSet<NodeIndex>> = vec![];
+    for edge in graph.edge_references() {
+        for sequence in edge.weight().sequences.ones() {
+            if next.len() <= sequence {
+                next.resize_with(sequence + 1, HashMap::new);
+                entered.resize_with(sequence + 1, HashSet::new);
+            }
+            next[sequence].insert(edge.source(), edge.target());
+            entered[sequence].insert(edge.target());
+        }
+    }
+
+    let isolated = graph
+        .node_indices()
+        .filter(|&node| graph.neighbors_undirected(node).next().is_none())
+        .map(|node| vec![node]);
+    next.iter()
+        .zip(&entered)
+        .map(|(next, entered)| {
+            let mut node = next.keys().find(|node| !entered.contains(node)).copied();
+            let mut path = vec![];
+            while let Some(current) = node {
+                path.push(current);
+                node = next.get(&current).copied();
+            }
+            path
+        })
+        .chain(isolated)
+        .collect()
+}
+
+/// Rolling hashes of every `k` consecutive token hashes
+fn kmer_hashes(hashes: &[u64], k: usize) -> Vec<u64> {
+    if hashes.len() < k {
+        return vec![];
+    }
+    let top = (1..k).fold(1u64, |power, _| power.wrapping_mul(BASE));
+    let mut hash = hashes[..k]
+        .iter()
+        .fold(0u64, |hash, &h| hash.wrapping_mul(BASE).wrapping_add(h));
+    let mut kmers = vec![hash];
+    for i in k..hashes.len() {
+        hash = hash
+            .wrapping_sub(hashes[i - k].wrapping_mul(top))
+            .wrapping_mul(BASE)
+            .wrapping_add(hashes[i]);
+        kmers.push(hash);
+    }
+    kmers
+}
+
+/// Exact matches of `k` tokens occurring once in the sequence and along a
+/// single one of the paths of the graph, overlapping matches along the same
+/// path are merged
+fn find_anchors<T: Token>(
+    seq: &[T],
+    graph: &DiGraph<NodeData<T>, EdgeData>,
+    paths: &[Vec<NodeIndex>],
+    k: usize,
+) -> Vec<Anchor> {
+    let state = RandomState::new();
+    let hashes: Vec<u64> = seq.iter().map(|token| state.hash_one(token)).collect();
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for hash in kmer_hashes(&hashes, k) {
+        *counts.entry(hash).or_default() += 1;
+    }
+
+    // first path and offset of each k-mer of the graph, None when it
+    // occurs along different nodes
+    let mut kmers: HashMap<u64, Option<(usize, usize)>> = HashMap::new();
+    for (p, path) in paths.iter().enumerate() {
+        let hashes: Vec<u64> = path
+            .iter()
+            .map(|&node| state.hash_one(&graph[node].item))
+            .collect();
+        for (offset, hash) in kmer_hashes(&hashes, k).into_iter().enumerate() {
+            if counts.get(&hash) != Some(&1) {
+                continue;
+            }
+            match kmers.entry(hash) {
+                Entry::Vacant(entry) => {
+                    entry.insert(Some((p, offset)));
+                }
+                Entry::Occupied(mut entry) => {
+                    if let Some((q, first)) = *entry.get() {
+                        if paths[q][first..first + k] != path[offset..offset + k] {
+                            entry.insert(None);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut anchors: Vec<Anchor> = vec![];
+    for (start, hash) in kmer_hashes(&hashes, k).into_iter().enumerate() {
+        let Some(&Some((p, offset))) = kmers.get(&hash) else {
+            continue;
+        };
+        let nodes = &paths[p][offset..offset + k];
+        // hashes may collide
+        if !nodes
+            .iter()
+            .zip(&seq[start..])
+            .all(|(&node, token)| graph[node].item == *token)
+        {
+            continue;
+        }
+        match anchors.last_mut() {
+            Some(last)
+                if last.end() - k + 1 == start
+                    && last.nodes[last.nodes.len() - k + 1..] == nodes[..k - 1]
+                    && graph.contains_edge(last.nodes[last.nodes.len() - 1], nodes[k - 1]) =>
+            {
+                last.nodes.push(nodes[k - 1]);
+            }
+            _ => anchors.push(Anchor {
+                start,
+                nodes: nodes.to_vec(),
+            }),
+        }
+    }
+    anchors
+}
+
+/// Heaviest chain of anchors in order along both the sequence and the
+/// topological order of the graph, weighed by the tokens they cover
+fn chain(anchors: Vec<Anchor>, rank: &[usize]) -> Vec<Anchor> {
+    let first = |anchor: &Anchor| rank[anchor.nodes[0].index()];
+    let last = |anchor: &Anchor| rank[anchor.nodes[anchor.nodes.len() - 1].index()];
+
+    let mut best: Vec<(usize, Option<usize>)> = Vec::with_capacity(anchors.len());
+    for (b, anchor) in anchors.iter().enumerate() {
+        let from = (0..b)
+            .filter(|&a| anchors[a].end() <= anchor.start && last(&anchors[a]) < first(anchor))
+            .max_by_key(|&a| best[a].0);
+        let covered = from.map_or(0, |a| best[a].0) + anchor.nodes.len();
+        best.push((covered, from));
+    }
+
+    let mut chained = vec![];
+    let mut at = (0..anchors.len()).max_by_key(|&b| best[b].0);
+    while let Some(b) = at {
+        chained.push(b);
+        at = best[b].1;
+    }
+    let mut anchors: Vec<Option<Anchor>> = anchors.into_iter().map(Some).collect();
+    chained
+        .into_iter()
+        .rev()
+        .map(|b| anchors[b].take().unwrap())
+        .collect()
+}
+
+/// Nodes on paths from `from` to `to` in topological order, all ancestors
+/// of `to` or all descendants of `from` when the other is not given. None
+/// when `to` cannot be reached from `from`.
+fn nodes_between<T>(
+    graph: &DiGraph<NodeData<T>, EdgeData>,
+    rank: &[usize],
+    from: Option<NodeIndex>,
+    to: Option<NodeIndex>,
+) -> Option<Vec<NodeIndex>> {
+    let reach = |start: NodeIndex, direction: Direction, within: &dyn Fn(NodeIndex) -> bool| {
+        let mut seen = HashSet::from([start]);
+        let mut stack = vec![start];
+        while let Some(node) = stack.pop() {
+            for next in graph.neighbors_directed(node, direction) {
+                if within(next) && seen.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        seen
+    };
+    let rank_of = |node: NodeIndex| rank[node.index()];
+
+    let mut nodes: Vec<NodeIndex> = match (from, to) {
+        (Some(from), Some(to)) => {
+            let after = reach(from, Direction::Outgoing, &|node| {
+                rank_of(node) <= rank_of(to)
+            });
+            if !after.contains(&to) {
+                return None;
+            }
+            let before = reach(to, Direction::Incoming, &|node| after.contains(&node));
+            before.into_iter().collect()
+        }
+        (Some(from), None) => reach(from, Direction::Outgoing, &|_| true)
+            .into_iter()
+            .collect(),
+        (None, Some(to)) => reach(to, Direction::Incoming, &|_| true)
+            .into_iter()
+            .collect(),
+        (None, None) => graph.node_indices().collect(),
+    };
+    nodes.sort_by_key(|&node| rank_of(node));
+    Some(nodes)
+}
+
+/// Token of the part of a sequence between two anchors, the anchors at
+/// either end are replaced by stand-ins only matching each other
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Segment<'a, T> {
+    Token(&'a T),
+    Anchor,
+}
+
+/// Scores segments with the scorer for the tokens, the stand-ins for the
+/// anchors score `anchor_score` aligned to each other
+struct SegmentScorer<'s, S: ?Sized> {
+    scorer: &'s S,
+    anchor_score: i32,
+}
+
+impl<T: Token, S: Scorer<T> + ?Sized> Scorer<Segment<'_, T>> for SegmentScorer<'_, S> {
+    fn score(&self, a: &Segment<T>, b: &Segment<T>) -> i32 {
+        match (a, b) {
+            (Segment::Token(a), Segment::Token(b)) => self.scorer.score(a, b),
+            (Segment::Anchor, Segment::Anchor) => self.anchor_score,
+            _ => -self.anchor_score,
+        }
+    }
+
+    fn gap_open(&self) -> i32 {
+        self.scorer.gap_open()
+    }
+
+    fn gap_extend(&self) -> i32 {
+        self.scorer.gap_extend()
+    }
+
+    fn is_match(&self, a: &Segment<T>, b: &Segment<T>) -> bool {
+        match (a, b) {
+            (Segment::Token(a), Segment::Token(b)) => self.scorer.is_match(a, b),
+            (Segment::Anchor, Segment::Anchor) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Align the sequence between the last token of one anchor and the first
+/// token of the next to the nodes between them, Some(None) when the anchors
+/// are not connected. The anchors are aligned along with it, so that the
+/// alignment has to start and end at them, and are left out of the result.
+/// None when the scores of the anchors would not fit the DP.
+fn align_between<T: Token, S: Scorer<T> + ?Sized>(
+    seq: &[T],
+    graph: &DiGraph<NodeData<T>, EdgeData>,
+    rank: &[usize],
+    scorer: &S,
+    options: AlignmentOptions,
+    from: Option<(usize, NodeIndex)>,
+    to: Option<(usize, NodeIndex)>,
+) -> Option<Option<Traceback>> {
+    let anchors = [from, to].map(|anchor| anchor.map(|anchor| anchor.1));
+    let Some(nodes) = nodes_between(graph, rank, anchors[0], anchors[1]) else {
+        return Some(None);
+    };
+    let mut subgraph = DiGraph::new();
+    let mut sub_index = HashMap::new();
+    for &node in &nodes {
+        let item = if anchors.contains(&Some(node)) {
+            Segment::Anchor
+        } else {
+            Segment::Token(&graph[node].item)
+        };
+        let index = subgraph.add_node(NodeData {
+            item,
+            aligned_to: vec![],
+        });
+        sub_index.insert(node, index);
+    }
+    for &node in &nodes {
+        for next in graph.neighbors(node) {
+            if let Some(&next) = sub_index.get(&next) {
+                subgraph.add_edge(sub_index[&node], next, EdgeData::new(0));
+            }
+        }
+    }
+
+    // stand-ins take the places of the last and first tokens of the anchors
+    let start = from.map_or(0, |a| a.0 + 1);
+    let end = to.map_or(seq.len(), |b| b.0);
+    let segment: Vec<Segment<T>> = from
+        .map(|_| Segment::Anchor)
+        .into_iter()
+        .chain(seq[start..end].iter().map(Segment::Token))
+        .chain(to.map(|_| Segment::Anchor))
+        .collect();
+    // segment positions are shifted by the stand-in for the anchor before it
+    let offset = start as i32 - i32::from(from.is_some());
+
+    // no step of an alignment of the tokens scores further from zero than
+    // `step`, so matching both stand-ins beats matching one of them
+    let tokens: HashSet<&T> = seq[start..end].iter().collect();
+    let node_tokens: HashSet<&T> = nodes
+        .iter()
+        .filter(|&&node| !anchors.contains(&Some(node)))
+        .map(|&node| &graph[node].item)
+        .collect();
+    let step = tokens
+        .iter()
+        .flat_map(|a| {
+            node_tokens
+                .iter()
+                .map(|b| scorer.score(a, b).unsigned_abs())
+        })
+        .chain([scorer.gap_open().unsigned_abs() + scorer.gap_extend().unsigned_abs()])
+        .max()
+        .unwrap_or(0);
+    let bound = (segment.len() + nodes.len()) as u64 * u64::from(step);
+    // far enough from the DP's NEG_INF for any sum of scores of the segment
+    let anchor_score = 2 * bound + 1;
+    if anchor_score >= 1 << 28 {
+        return None;
+    }
+    let scorer = SegmentScorer {
+        scorer,
+        anchor_score: anchor_score as i32,
+    };
+
+    // the nodes were added in topological order
+    let order = subgraph.node_indices().collect();
+    let (score, positions, aligned_nodes) =
+        SeqGraphAlignment::align_dp(&segment, &subgraph, order, &scorer, options);
+
+    let (positions, nodes) = positions
+        .iter()
+        .zip(&aligned_nodes)
+        .filter(|&(_, node)| !node.is_some_and(|node| subgraph[node].item == Segment::Anchor))
+        .map(|(&position, &node)| {
+            (
+                position.map(|position| position + offset),
+                node.map(|node| nodes[node.index()]),
+            )
+        })
+        .unzip();
+    let matched_anchors = anchors.iter().flatten().count() as i32;
+    Some(Some((
+        score - matched_anchors * scorer.anchor_score,
+        positions,
+        nodes,
+    )))
+}
+
+/// Global alignment of the sequence that only runs the DP between exact
+/// matches of `k` tokens chained along the sequence and the graph, `order`
+/// lists the nodes of the graph in topological order and `paths` those of
+/// each of its sequences, found from the graph when not given. None when no
+/// anchors are found or the scores between them would not fit the DP, the
+/// full DP is then needed.
+pub fn align_anchored<T: Token, S: Scorer<T> + ?Sized>(
+    seq: &[T],
+    graph: &DiGraph<NodeData<T>, EdgeData>,
+    order: &[NodeIndex],
+    paths: Option<&[Vec<NodeIndex>]>,
+    scorer: &S,
+    options: AlignmentOptions,
+    k: usize,
+) -> Option<Traceback> {
+    if k == 0 {
+        return None;
+    }
+    let found;
+    let paths = match paths {
+        Some(paths) => paths,
+        None => {
+            found = sequence_paths(graph);
+            &found
+        }
+    };
+    let mut rank = vec![0; graph.node_count()];
+    for (position, &node) in order.iter().enumerate() {
+        rank[node.index()] = position;
+    }
+    // the ends of anchors are left to the DP, which may place a gap next
+    // to an anchor a few tokens into it
+    let trim = k / 4;
+    let anchors: Vec<Anchor> = chain(find_anchors(seq, graph, paths, k), &rank)
+        .into_iter()
+        .map(|anchor| Anchor {
+            start: anchor.start + trim,
+            nodes: anchor.nodes[trim..anchor.nodes.len() - trim].to_vec(),
+        })
+        .collect();
+    if anchors.is_empty() {
+        return None;
+    }
+
+    let (mut score, mut positions, mut nodes) = (0, vec![], vec![]);
+    let mut from = None;
+    for anchor in anchors.iter().map(Some).chain([None]) {
+        let to = anchor.map(|anchor| (anchor.start, anchor.nodes[0]));
+        // skip anchors on branches the previous anchor does not lead to,
+        // the full DP aligns segments too long for the scores
+        let Some((gap_score, gap_positions, gap_nodes)) =
+            align_between(seq, graph, &rank, scorer, options, from, to)?
+        else {
+            continue;
+        };
+        score += gap_score;
+        positions.extend(gap_positions);
+        nodes.extend(gap_nodes);
+
+        if let Some(anchor) = anchor {
+            for (position, &node) in (anchor.start..).zip(&anchor.nodes) {
+                score += scorer.score(&seq[position], &graph[node].item);
+                positions.push(Some(position as i32));
+                nodes.push(Some(node));
+            }
+            from = Some((anchor.end() - 1, *anchor.nodes.last().unwrap()));
+        }
+    }
+    Some((score, positions, nodes))
+}
+
+#[cfg(test)]
+mod anchors_tests {
+    use super::{chain, find_anchors, sequence_paths, Anchor};
+    use crate::{
+        alignment::{AlignmentMode, AlignmentOptions},
+        graph::POAGraph,
+        scoring::Scoring,
+        test_utils::{graph_of, to_seq, Rng},
+    };
+    use petgraph::graph::NodeIndex;
+
+    #[test]
+    fn find_anchors_test() {
+        let graph = graph_of(&[("seq_1", "ACGTTGCAAGGC")]);
+        // ACG occurs twice in the sequence, TGC, GCA and CAA merge into one
+        let anchors = find_anchors(&to_seq("ACGTGCAACG"), &graph.graph, &graph.paths, 3);
+        assert_eq!(
+            anchors,
+            vec![
+                Anchor {
+                    start: 1,
+                    nodes: (1..4).map(NodeIndex::new).collect(),
+                },
+                Anchor {
+                    start: 3,
+                    nodes: (4..9).map(NodeIndex::new).collect(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn chain_test() {
+        let anchor = |start: usize, nodes: [usize; 2]| Anchor {
+            start,
+            nodes: nodes.into_iter().map(NodeIndex::new).collect(),
+        };
+        let rank: Vec<usize> = (0..10).collect();
+        // the middle anchor is out of order along the graph
+        let anchors = vec![
+            anchor(0, [0, 1]),
+            anchor(2, [7, 8]),
+            anchor(4, [3, 4]),
+            anchor(6, [5, 6]),
+        ];
+        assert_eq!(
+            chain(anchors, &rank),
+            vec![anchor(0, [0, 1]), anchor(4, [3, 4]), anchor(6, [5, 6])]
+        );
+    }
+
+    #[test]
+    fn anchored_alignment_test() {
+        let mut rng = Rng::new(7);
+        let reference: Vec<String> = (0..400).map(|_| rng.base()).collect();
+        // copies with a few substitutions, deletions and insertions
+        let variants: Vec<_> = (0..4).map(|_| rng.mutate(&reference, 100)).collect();
+
+        // scores far larger than those of the tokens between anchors keep the
+        // anchors matched to each other
+        for scoring in [
+            Scoring::default(),
+            Scoring::new(1 << 19, -(1 << 19), 0, -(1 << 20)),
+        ] {
+            let mut graph = POAGraph::new("reference".to_string(), reference.clone()).unwrap();
+            let options = AlignmentOptions {
+                anchor: Some(12),
+                ..Default::default()
+            };
+            for (i, variant) in variants.iter().cloned().enumerate() {
+                let label = format!("variant_{}", i);
+                let full = graph
+                    .align(
+                        label.clone(),
+                        variant.clone(),
+                        &scoring,
+                        AlignmentMode::Global.into(),
+                    )
+                    .unwrap();
+                let anchored = graph
+                    .align(label.clone(), variant.clone(), &scoring, options)
+                    .unwrap();
+                assert_eq!(anchored.score(), full.score());
+                assert_eq!(
+                    anchored.seq_match_positions.iter().flatten().count(),
+                    anchored.seq.len()
+                );
+                graph
+                    .add_sequence(label, variant, &scoring, options)
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn sequence_paths_test() {
+        let graph = graph_of(&[("seq_1", "ACGT"), ("seq_2", "AGGT"), ("seq_3", "C")]);
+        let node = NodeIndex::new;
+        assert_eq!(
+            graph.paths,
+            vec![
+                vec![node(0), node(1), node(2), node(3)],
+                vec![node(0), node(4), node(2), node(3)],
+                vec![node(1)],
+            ]
+        );
+        // a single token on a node with edges cannot be told from the edges
+        assert_eq!(sequence_paths(&graph.graph), graph.paths[..2]);
+
+        // the path of a single token sequence is anchored along
+        let graph = graph_of(&[("seq_1", "G"), ("seq_2", "T")]);
+        assert_eq!(
+            sequence_paths(&graph.graph),
+            vec![vec![node(0)], vec![node(1)]]
+        );
+        let anchors = find_anchors(&to_seq("AGA"), &graph.graph, &graph.paths, 1);
+        assert_eq!(
+            anchors,
+            vec![Anchor {
+                start: 1,
+                nodes: vec![node(0)],
+            }]
+        );
+    }
+
+    #[test]
+    fn large_scores_anchored_test() {
+        // anchors only along the distinct tokens at the start, the run of
+        // A after them is left to one long segment
+        let reference: Vec<String> = (0..30)
+            .map(|i| format!("t{}", i))
+            .chain((0..1500).map(|_| "A".to_string()))
+            .collect();
+        let mut variant = reference.clone();
+        variant.insert(800, "G".to_string());
+        variant.insert(10, "C".to_string());
+
+        // scores of the long segment do not fit next to the anchors
+        let scoring = Scoring::new(100_000, -100_000, 0, -100_000);
+        let graph = POAGraph::new("reference".to_string(), reference).unwrap();
+        let full = graph
+            .align(
+                "variant".to_string(),
+                variant.clone(),
+                &scoring,
+                AlignmentMode::Global.into(),
+            )
+            .unwrap();
+        let anchored = graph
+            .align(
+                "variant".to_string(),
+                variant,
+                &scoring,
+                AlignmentOptions {
+                    anchor: Some(8),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(anchored.score(), full.score());
+        assert_eq!(anchored.seq_match_positions, full.seq_match_positions);
+    }
+}
@@ -1,4 +1,64 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use generic_poa::{io::Tokeniser, AlignmentMode, TieBreak};
+
+/// Tokenisation of FASTA record bodies
+#[derive(ValueEnum, Clone, Debug)]
+pub enum Tokenise {
+    /// One token per character, whitespace is skipped
+    Char,
+    /// Tokens separated by whitespace
+    Whitespace,
+    /// Tokens separated by --delimiter
+    Delimiter,
+}
+
+/// Alignment mode of sequences against the graph
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Mode {
+    /// Align whole sequences end to end
+    Global,
+    /// Align the best matching parts only
+    Local,
+    /// Align whole sequences to any part of the graph
+    Fitting,
+    /// Align part of each sequence to a whole path of the graph
+    Containment,
+    /// Align overlapping ends of sequences and the graph
+    Overlap,
+}
+
+/// Which of equally scoring alignments is taken
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Ties {
+    /// Place gaps as far right as they go
+    RightGaps,
+    /// Place gaps as far left as they go
+    LeftGaps,
+    /// Align identical tokens first, then gaps, then mismatches
+    Matches,
+}
+
+impl From<Mode> for AlignmentMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Global => AlignmentMode::Global,
+            Mode::Local => AlignmentMode::Local,
+            Mode::Fitting => AlignmentMode::Fitting,
+            Mode::Containment => AlignmentMode::Containment,
+            Mode::Overlap => AlignmentMode::Overlap,
+        }
+    }
+}
+
+impl From<Ties> for TieBreak {
+    fn from(ties: Ties) -> Self {
+        match ties {
+            Ties::RightGaps => TieBreak::RightGaps,
+            Ties::LeftGaps => TieBreak::LeftGaps,
+            Ties::Matches => TieBreak::Matches,
+        }
+    }
+}
 
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
@@ -20,7 +80,139 @@ pub struct POACli {
     #[arg(long)]
     pub graph: bool,
 
+    /// Enable consensus output
+    #[arg(long)]
+    pub consensus: bool,
+
+    /// Enable alignment stats output
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Alignment mode
+    #[arg(long, value_enum, default_value_t = Mode::Global)]
+    pub mode: Mode,
+
+    /// Which of equally scoring alignments is taken
+    #[arg(long, value_enum, default_value_t = Ties::RightGaps)]
+    pub tie_break: Ties,
+
+    /// Only compute DP cells within this many columns of the expected
+    /// position of each node, widening the band up to the full DP while it
+    /// may clip the best alignment
+    #[arg(long, value_name = "WIDTH")]
+    pub band: Option<usize>,
+
+    /// Let the band follow the best scoring cells of the preceding nodes
+    /// instead of the diagonal
+    #[arg(long, requires = "band")]
+    pub adaptive_band: bool,
+
+    /// Memory the full DP of one alignment may use, with a K, M or G
+    /// suffix. Larger alignments keep checkpoint rows and fill the DP
+    /// again while backtracking
+    #[arg(long, value_name = "SIZE", value_parser = parse_memory)]
+    pub max_memory: Option<usize>,
+
+    /// Anchor global alignments at exact matches of this many tokens that
+    /// occur once in the sequence and the graph, only aligning between them
+    #[arg(long, value_name = "K")]
+    pub anchor: Option<usize>,
+
+    /// Align the sequences of this file to the graph of the input without
+    /// adding them, writing each alignment and its score to
+    /// <OUTPUT>.queries.tsv
+    #[arg(long, value_name = "FILE")]
+    pub queries: Option<String>,
+
+    /// Threads for aligning queries, all cores by default
+    #[arg(long, requires = "queries")]
+    pub threads: Option<usize>,
+
+    /// Score for aligning identical tokens
+    #[arg(
+        long = "match",
+        value_name = "SCORE",
+        default_value_t = 1,
+        allow_hyphen_values = true
+    )]
+    pub match_score: i32,
+
+    /// Score for aligning different tokens
+    #[arg(long = "mismatch", value_name = "SCORE", default_value_t = -1, allow_hyphen_values = true)]
+    pub mismatch_score: i32,
+
+    /// Score for each gap position in either the sequence or the graph
+    #[arg(long, value_name = "SCORE", default_value_t = -2, allow_hyphen_values = true)]
+    pub gap: i32,
+
+    /// Additional score for opening a gap, 0 gives linear gap penalties
+    #[arg(
+        long,
+        value_name = "SCORE",
+        default_value_t = 0,
+        allow_hyphen_values = true
+    )]
+    pub gap_open: i32,
+
+    /// Substitution matrix file replacing --match and --mismatch, pairs not
+    /// in the matrix score --mismatch
+    #[arg(long, conflicts_with_all = ["ignore_case", "fuzzy"])]
+    pub matrix: Option<String>,
+
+    /// Treat tokens differing only in case as matches
+    #[arg(long, conflicts_with = "fuzzy")]
+    pub ignore_case: bool,
+
+    /// Score tokens by normalised edit distance, tokens at least this
+    /// similar (0 to 1) partially match and share a node
+    #[arg(long, value_name = "THRESHOLD")]
+    pub fuzzy: Option<f64>,
+
     /// Display intermediate alignments
     #[arg(long)]
     pub debug: bool,
+
+    /// Also display up to this many alignments scoring as well as each
+    /// intermediate alignment, found with the full DP
+    #[arg(long, value_name = "N", requires = "debug")]
+    pub co_optimal: Option<usize>,
+
+    /// Warn about duplicate or empty sequence labels
+    #[arg(long)]
+    pub warn_labels: bool,
+
+    /// Tokenisation of FASTA record bodies
+    #[arg(long, value_enum, default_value_t = Tokenise::Char)]
+    pub tokenise: Tokenise,
+
+    /// Token delimiter for FASTA records when using --tokenise delimiter
+    #[arg(long, default_value = ",")]
+    pub delimiter: String,
+}
+
+impl POACli {
+    /// Tokeniser of FASTA record bodies, splitting at --delimiter for
+    /// `Tokenise::Delimiter`
+    pub fn tokeniser(&self) -> Tokeniser {
+        match self.tokenise {
+            Tokenise::Char => Tokeniser::Char,
+            Tokenise::Whitespace => Tokeniser::Whitespace,
+            Tokenise::Delimiter => Tokeniser::Delimiter(self.delimiter.clone()),
+        }
+    }
+}
+
+/// Parse a byte count with an optional K, M or G suffix
+fn parse_memory(size: &str) -> Result<usize, String> {
+    let (digits, scale) = match size.char_indices().last() {
+        Some((at, 'k' | 'K')) => (&size[..at], 1 << 10),
+        Some((at, 'm' | 'M')) => (&size[..at], 1 << 20),
+        Some((at, 'g' | 'G')) => (&size[..at], 1 << 30),
+        _ => (size, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|count| count.checked_mul(scale))
+        .ok_or_else(|| format!("invalid memory size '{}'", size))
 }
@@ -1,23 +1,36 @@
-use crate::graph::{EdgeData, NodeData};
+use crate::graph::{topological_order, EdgeData, GraphError, NodeData, SequenceId, Token};
 use petgraph::{
-    algo::toposort,
     graph::{DiGraph, NodeIndex},
     visit::EdgeRef,
     Direction,
 };
 use std::collections::HashMap;
 
-pub struct Consensus {
-    graph: DiGraph<NodeData, EdgeData>,
-    start_indices: Vec<NodeIndex>,
-    labels: Vec<String>,
+/// The heaviest path through the graph
+#[derive(Debug)]
+pub struct ConsensusPath<T = String> {
+    pub items: Vec<T>,
+    pub node_indices: Vec<NodeIndex>,
+    /// Number of sequences passing through each node of the path
+    pub support: Vec<usize>,
 }
 
-impl Consensus {
+/// Label of a sequence and its row of the multiple sequence alignment,
+/// `None` marks a gap
+pub type MsaRow<T> = (String, Vec<Option<T>>);
+
+/// Multiple sequence alignment and consensus of the sequences of a graph
+pub struct Consensus<'a, T = String> {
+    graph: &'a DiGraph<NodeData<T>, EdgeData>,
+    start_indices: &'a [NodeIndex],
+    labels: &'a [String],
+}
+
+impl<'a, T: Token> Consensus<'a, T> {
     pub fn new(
-        graph: DiGraph<NodeData, EdgeData>,
-        start_indices: Vec<NodeIndex>,
-        labels: Vec<String>,
+        graph: &'a DiGraph<NodeData<T>, EdgeData>,
+        start_indices: &'a [NodeIndex],
+        labels: &'a [String],
     ) -> Self {
         Self {
             graph,
@@ -26,11 +39,13 @@ impl Consensus {
         }
     }
 
-    pub fn compute(&self) -> Vec<(String, Vec<String>)> {
+    /// Rows of the multiple sequence alignment, an error when the graph
+    /// has a cycle
+    pub fn compute(&self) -> Result<Vec<MsaRow<T>>, GraphError> {
         // Step 1: assign node IDs to columns in the output
         //      column_index[node.ID] is the position in the toposorted node list
         //      of the node itself, or the earliest node it is aligned to.
-        let indices = toposort(&self.graph, None).unwrap();
+        let indices = topological_order(self.graph)?;
         let mut column_index: HashMap<NodeIndex, i32> = HashMap::new();
         let mut current_column = 0;
 
@@ -56,68 +71,185 @@ impl Consensus {
         //      corresponding to the sequences inserted in the graph
         let mut labels = vec![];
         let mut alignment_strings = vec![];
-        let mut current_node_index_option: Option<NodeIndex>;
 
-        for (label, &start) in self.labels.iter().zip(&self.start_indices) {
+        for (sequence, (label, &start)) in self.labels.iter().zip(self.start_indices).enumerate() {
             labels.push(label.clone());
-            current_node_index_option = Some(start);
-            let mut item_list: Vec<String> =
-                (0..current_column).map(|_| String::from("-")).collect();
-
-            while let Some(current_node_index) = current_node_index_option {
-                current_node_index_option = None;
-                item_list[column_index[&current_node_index] as usize]
-                    .clone_from(&self.graph[current_node_index].item);
-                // iterate all out going edges
-                for edge in self
-                    .graph
-                    .edges_directed(current_node_index, Direction::Outgoing)
-                {
-                    // found the edge with same label
-                    if edge.weight().labels.iter().any(|item| item == label) {
-                        current_node_index_option = Some(edge.target());
-                        break;
-                    }
-                }
+            let mut item_list: Vec<Option<T>> = vec![None; current_column as usize];
+
+            for node_index in self.sequence_path(sequence, start) {
+                item_list[column_index[&node_index] as usize] =
+                    Some(self.graph[node_index].item.clone());
             }
 
             alignment_strings.push(item_list);
         }
-        labels.into_iter().zip(alignment_strings).collect()
+        Ok(labels.into_iter().zip(alignment_strings).collect())
+    }
+
+    /// Heaviest bundle consensus (Lee 2003). Each node picks the outgoing
+    /// edge maximising (edge weight, best path score from the target), where
+    /// the weight of an edge is the number of sequences supporting it. The
+    /// consensus is the path starting from the highest scoring node.
+    pub fn heaviest_bundle(&self) -> Result<ConsensusPath<T>, GraphError> {
+        let indices = topological_order(self.graph)?;
+        let mut scores: HashMap<NodeIndex, usize> = HashMap::new();
+        let mut next_in_path: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+
+        for &index in indices.iter().rev() {
+            let mut best: Option<(usize, usize, NodeIndex)> = None;
+
+            for edge in self.graph.edges_directed(index, Direction::Outgoing) {
+                let candidate = (
+                    edge.weight().weight(),
+                    scores[&edge.target()],
+                    edge.target(),
+                );
+                if best.is_none_or(|best| candidate > best) {
+                    best = Some(candidate);
+                }
+            }
+
+            if let Some((weight, score, target)) = best {
+                scores.insert(index, weight + score);
+                next_in_path.insert(index, target);
+            } else {
+                scores.insert(index, 0);
+            }
+        }
+
+        // the first node in topological order wins ties
+        let mut current = indices.iter().copied().reduce(|best, index| {
+            if scores[&index] > scores[&best] {
+                index
+            } else {
+                best
+            }
+        });
+
+        let mut support: HashMap<NodeIndex, usize> = HashMap::new();
+        for (sequence, &start) in self.start_indices.iter().enumerate() {
+            for node_index in self.sequence_path(sequence, start) {
+                *support.entry(node_index).or_insert(0) += 1;
+            }
+        }
+
+        let mut path = ConsensusPath {
+            items: vec![],
+            node_indices: vec![],
+            support: vec![],
+        };
+
+        while let Some(index) = current {
+            path.items.push(self.graph[index].item.clone());
+            path.node_indices.push(index);
+            path.support.push(support.get(&index).copied().unwrap_or(0));
+            current = next_in_path.get(&index).copied();
+        }
+
+        Ok(path)
+    }
+
+    /// Nodes visited by the sequence
+    fn sequence_path(&self, sequence: SequenceId, start: NodeIndex) -> Vec<NodeIndex> {
+        let mut path = vec![];
+        let mut current_node_index_option = Some(start);
+
+        while let Some(current_node_index) = current_node_index_option {
+            current_node_index_option = None;
+            path.push(current_node_index);
+            // iterate all out going edges
+            for edge in self
+                .graph
+                .edges_directed(current_node_index, Direction::Outgoing)
+            {
+                // found the edge carrying the sequence
+                if edge.weight().contains(sequence) {
+                    current_node_index_option = Some(edge.target());
+                    break;
+                }
+            }
+        }
+        path
     }
 }
 
 #[cfg(test)]
 mod consensus_tests {
-    use crate::{alignment::SeqGraphAlignment, graph::POAGraph};
+    use crate::{
+        alignment::AlignmentMode,
+        graph::POAGraph,
+        scoring::Scoring,
+        test_utils::{graph_of, to_seq},
+    };
 
     use super::Consensus;
 
     #[test]
     fn consensus_test() {
-        let seq1 = vec![
-            "T".to_string(),
-            "G".to_string(),
-            "X".to_string(),
-            "T".to_string(),
-        ];
-        let seq2 = vec![
-            "A".to_string(),
-            "T".to_string(),
-            "G".to_string(),
-            "X".to_string(),
-            "T".to_string(),
-        ];
-        let seq3 = vec!["T".to_string(), "G".to_string(), "X".to_string()];
-        let mut graph = POAGraph::new("seq_1".to_string(), seq1);
-        let sg_aln = SeqGraphAlignment::align_seq_to_graph("seq_2".to_string(), seq2, &graph.graph);
-        graph.add_alignment(sg_aln);
-        let sg_aln = SeqGraphAlignment::align_seq_to_graph("seq_3".to_string(), seq3, &graph.graph);
-        graph.add_alignment(sg_aln);
-        let con = Consensus::new(graph.graph, graph.start_indices, graph.labels);
-        let alns = con.compute();
+        let graph = graph_of(&[("seq_1", "TGXT"), ("seq_2", "ATGXT"), ("seq_3", "TGX")]);
+        let con = Consensus::new(&graph.graph, &graph.start_indices, &graph.labels);
+        let alns = con.compute().unwrap();
         alns.iter().for_each(|v| {
             println!("{:?}", v);
         });
     }
+
+    #[test]
+    fn heaviest_bundle_test() {
+        let graph = graph_of(&[
+            ("seq_1", "TGXT"),
+            ("seq_2", "TGAXT"),
+            ("seq_3", "TGXT"),
+            ("seq_4", "TGX"),
+        ]);
+        let con = Consensus::new(&graph.graph, &graph.start_indices, &graph.labels);
+        let path = con.heaviest_bundle().unwrap();
+        assert_eq!(path.items, to_seq("TGXT"));
+        assert_eq!(path.support, vec![4, 4, 4, 3]);
+        assert_eq!(path.node_indices.len(), path.items.len());
+    }
+
+    #[test]
+    fn duplicate_label_test() {
+        let graph = graph_of(&[("dup", "TGXT"), ("dup", "TGAXT"), ("", "TGX"), ("", "GXT")]);
+        let con = Consensus::new(&graph.graph, &graph.start_indices, &graph.labels);
+        let rows: Vec<(String, String)> = con
+            .compute()
+            .unwrap()
+            .into_iter()
+            .map(|(label, row)| (label, row.into_iter().flatten().collect()))
+            .collect();
+        assert_eq!(rows[0], ("dup".to_string(), "TGXT".to_string()));
+        assert_eq!(rows[1], ("dup".to_string(), "TGAXT".to_string()));
+        assert_eq!(rows[2], ("".to_string(), "TGX".to_string()));
+        assert_eq!(rows[3], ("".to_string(), "GXT".to_string()));
+    }
+
+    #[test]
+    fn generic_token_test() {
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        enum Road {
+            Highway,
+            Street,
+            Lane,
+        }
+
+        let records = vec![
+            (
+                "trip_1".to_string(),
+                vec![Road::Highway, Road::Street, Road::Lane],
+            ),
+            ("trip_2".to_string(), vec![Road::Highway, Road::Lane]),
+        ];
+        let graph =
+            POAGraph::from_records(records, &Scoring::default(), AlignmentMode::Global.into())
+                .unwrap();
+        let con = Consensus::new(&graph.graph, &graph.start_indices, &graph.labels);
+        let alns = con.compute().unwrap();
+        assert_eq!(alns[1].1, vec![Some(Road::Highway), None, Some(Road::Lane)]);
+        assert_eq!(
+            con.heaviest_bundle().unwrap().items,
+            vec![Road::Highway, Road::Street, Road::Lane]
+        );
+    }
 }
@@ -0,0 +1,101 @@
+use crate::graph::GraphError;
+use std::{error::Error, fmt, io};
+
+/// Errors of reading the input, aligning and writing the output
+#[derive(Debug)]
+pub enum PoaError {
+    /// Reading or writing the file failed
+    Io { path: String, source: io::Error },
+    /// The file is malformed at the line and column, both counted from 1
+    Parse {
+        path: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// The file has no sequences, or the labelled sequence has no tokens
+    EmptyInput { path: String, label: Option<String> },
+    /// The graph is inconsistent
+    InvalidGraph(GraphError),
+    /// Options that are out of range or cannot be used together
+    Config(String),
+}
+
+impl PoaError {
+    /// Turn an I/O error on the file into a `PoaError`, for `map_err`
+    pub fn io(path: &str) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |source| Self::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    /// Name the file an error read from a reader was found in
+    pub fn in_file(mut self, file: &str) -> Self {
+        match &mut self {
+            Self::Io { path, .. } | Self::Parse { path, .. } | Self::EmptyInput { path, .. } => {
+                *path = file.to_string();
+            }
+            Self::InvalidGraph(_) | Self::Config(_) => {}
+        }
+        self
+    }
+
+    /// Exit code of the process for the kind of error, following sysexits
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Config(_) => 64,
+            Self::Parse { .. } => 65,
+            Self::EmptyInput { .. } => 66,
+            Self::InvalidGraph(_) => 70,
+            Self::Io { .. } => 74,
+        }
+    }
+}
+
+impl fmt::Display for PoaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // errors of records given directly have no file
+        let file = |path: &str| match path {
+            "" => String::new(),
+            path => format!("{}: ", path),
+        };
+        match self {
+            Self::Io { path, source } => write!(f, "{}{}", file(path), source),
+            Self::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => {
+                if !path.is_empty() {
+                    write!(f, "{}:", path)?;
+                }
+                write!(f, "{}:{}: {}", line, column, message)
+            }
+            Self::EmptyInput { path, label: None } => write!(f, "{}no sequences", file(path)),
+            Self::EmptyInput {
+                path,
+                label: Some(label),
+            } => write!(f, "{}sequence '{}' has no tokens", file(path), label),
+            Self::InvalidGraph(error) => write!(f, "invalid graph: {}", error),
+            Self::Config(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for PoaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::InvalidGraph(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<GraphError> for PoaError {
+    fn from(error: GraphError) -> Self {
+        Self::InvalidGraph(error)
+    }
+}
@@ -1,65 +1,241 @@
-use crate::alignment::SeqGraphAlignment;
-use petgraph::graph::{DiGraph, NodeIndex};
-use std::cmp::max;
+use crate::{
+    alignment::{AlignmentOptions, AlignmentStats, SeqGraphAlignment},
+    consensus::{Consensus, ConsensusPath, MsaRow},
+    error::PoaError,
+    scoring::Scorer,
+};
+use fixedbitset::FixedBitSet;
+use petgraph::{
+    algo::toposort,
+    graph::{DiGraph, NodeIndex},
+    visit::EdgeRef,
+    Direction,
+};
+use std::{collections::HashSet, fmt, hash::Hash};
+
+/// Anything that can be aligned as a token of a sequence
+pub trait Token: Eq + Hash + Clone {}
+
+impl<T: Eq + Hash + Clone> Token for T {}
 
 #[derive(Debug)]
-pub struct NodeData {
-    pub item: String,
+pub struct NodeData<T = String> {
+    pub item: T,
     pub aligned_to: Vec<NodeIndex>,
 }
 
+/// Internal id of a sequence, its index in `POAGraph.labels`. Labels are
+/// only for display and need not be unique
+pub type SequenceId = usize;
+
+/// Sequences threaded through an edge
 #[derive(Debug)]
 pub struct EdgeData {
-    pub labels: Vec<String>,
+    pub sequences: FixedBitSet,
 }
 
-pub struct POAGraph {
-    pub graph: DiGraph<NodeData, EdgeData>,
-    pub sequeces: Vec<Vec<String>>,
-    pub labels: Vec<String>,
-    pub start_indices: Vec<NodeIndex>,
-    pub width: usize,
+impl EdgeData {
+    pub fn new(sequence: SequenceId) -> Self {
+        let mut edge = Self {
+            sequences: FixedBitSet::new(),
+        };
+        edge.insert(sequence);
+        edge
+    }
+
+    pub fn insert(&mut self, sequence: SequenceId) {
+        self.sequences.grow(sequence + 1);
+        self.sequences.insert(sequence);
+    }
+
+    pub fn contains(&self, sequence: SequenceId) -> bool {
+        self.sequences.contains(sequence)
+    }
+
+    /// Number of sequences supporting the edge
+    pub fn weight(&self) -> usize {
+        self.sequences.count_ones(..)
+    }
 }
 
-impl POAGraph {
-    /// Initialise the POA graph with the first sequence
-    pub fn new(label: String, seq: Vec<String>) -> Self {
-        let mut width = 0usize;
-        let mut graph = DiGraph::new();
-        let nodes: Vec<NodeIndex> = seq
-            .iter()
-            .map(|item| {
-                width = max(item.len(), width);
-                graph.add_node(NodeData {
-                    item: item.clone(),
-                    aligned_to: vec![],
-                })
-            })
-            .collect();
-        for (position, &index) in nodes.iter().enumerate() {
-            if position < nodes.len() - 1 {
-                graph.add_edge(
-                    index,
-                    nodes[position + 1],
-                    EdgeData {
-                        labels: vec![label.clone()],
-                    },
-                );
+/// Inconsistency found in a graph, naming the nodes and sequence labels
+/// involved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+    /// The graph has a cycle through the node
+    Cycle(NodeIndex),
+    /// The first node is aligned to the second, which is not aligned to it
+    AsymmetricAlignment(NodeIndex, NodeIndex),
+    /// The path of the sequence enters or leaves the node along more than
+    /// one edge, or enters the node it starts at
+    PathFork { label: String, node: NodeIndex },
+    /// An edge of the sequence leaves the node, which is not on the path
+    /// from its start
+    PathBreak { label: String, node: NodeIndex },
+    /// The path of the sequence ends at the node after `length` of its
+    /// `expected` tokens
+    PathLength {
+        label: String,
+        node: NodeIndex,
+        length: usize,
+        expected: usize,
+    },
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Cycle(node) => write!(f, "graph has a cycle through node {}", node.index()),
+            Self::AsymmetricAlignment(node, other) => write!(
+                f,
+                "node {} is aligned to node {}, which is not aligned to it",
+                node.index(),
+                other.index()
+            ),
+            Self::PathFork { label, node } => {
+                write!(f, "sequence '{}' forks at node {}", label, node.index())
             }
+            Self::PathBreak { label, node } => write!(
+                f,
+                "sequence '{}' continues from node {}, off the path from its start",
+                label,
+                node.index()
+            ),
+            Self::PathLength {
+                label,
+                node,
+                length,
+                expected,
+            } => write!(
+                f,
+                "sequence '{}' ends at node {} after {} of its {} tokens",
+                label,
+                node.index(),
+                length,
+                expected
+            ),
         }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+/// Nodes of the graph in topological order
+pub fn topological_order<T>(
+    graph: &DiGraph<NodeData<T>, EdgeData>,
+) -> Result<Vec<NodeIndex>, GraphError> {
+    toposort(graph, None).map_err(|cycle| GraphError::Cycle(cycle.node_id()))
+}
+
+pub struct POAGraph<T = String> {
+    pub graph: DiGraph<NodeData<T>, EdgeData>,
+    pub sequences: Vec<Vec<T>>,
+    pub labels: Vec<String>,
+    pub start_indices: Vec<NodeIndex>,
+    /// Nodes each sequence passes through in order, for anchoring
+    pub(crate) paths: Vec<Vec<NodeIndex>>,
+}
+
+impl<T: Token> POAGraph<T> {
+    /// Graph without any sequences, the first one added is taken as it is
+    pub fn empty() -> Self {
         Self {
-            graph,
-            sequeces: vec![seq],
-            labels: vec![label],
-            start_indices: vec![nodes[0]],
-            width,
+            graph: DiGraph::new(),
+            sequences: vec![],
+            labels: vec![],
+            start_indices: vec![],
+            paths: vec![],
+        }
+    }
+
+    /// Initialise the POA graph with the first sequence, which may not be
+    /// empty
+    pub fn new(label: String, seq: Vec<T>) -> Result<Self, PoaError> {
+        let mut poa = Self::empty();
+        poa.add_unaligned(label, seq)?;
+        Ok(poa)
+    }
+
+    /// Build the graph from the records, added in order
+    pub fn from_records<S: Scorer<T> + ?Sized>(
+        records: Vec<(String, Vec<T>)>,
+        scorer: &S,
+        options: AlignmentOptions,
+    ) -> Result<Self, PoaError> {
+        let mut poa = Self::empty();
+        for (label, seq) in records {
+            poa.add_sequence(label, seq, scorer, options)?;
+        }
+        if poa.labels.is_empty() {
+            return Err(PoaError::EmptyInput {
+                path: String::new(),
+                label: None,
+            });
         }
+        Ok(poa)
     }
 
-    pub fn add_alignment(&mut self, aln: SeqGraphAlignment) {
+    /// Align the sequence to the graph and add it, giving the stats of its
+    /// alignment. None when the graph was empty and the sequence starts it.
+    pub fn add_sequence<S: Scorer<T> + ?Sized>(
+        &mut self,
+        label: String,
+        seq: Vec<T>,
+        scorer: &S,
+        options: AlignmentOptions,
+    ) -> Result<Option<AlignmentStats>, PoaError> {
+        if self.graph.node_count() == 0 {
+            self.add_unaligned(label, seq)?;
+            return Ok(None);
+        }
+        let aln = self.align(label, seq, scorer, options)?;
+        let stats = aln.stats();
+        self.add_alignment(aln, scorer);
+        Ok(Some(stats))
+    }
+
+    /// Align the sequence to the graph without adding it
+    pub fn align<S: Scorer<T> + ?Sized>(
+        &self,
+        label: String,
+        seq: Vec<T>,
+        scorer: &S,
+        options: AlignmentOptions,
+    ) -> Result<SeqGraphAlignment<T>, PoaError> {
+        if seq.is_empty() {
+            return Err(PoaError::EmptyInput {
+                path: String::new(),
+                label: Some(label),
+            });
+        }
+        Ok(SeqGraphAlignment::align_with_paths(
+            label,
+            seq,
+            &self.graph,
+            Some(&self.paths),
+            scorer,
+            options,
+        )?)
+    }
+
+    /// Rows of the multiple sequence alignment of the sequences in the
+    /// order they were added
+    pub fn msa(&self) -> Result<Vec<MsaRow<T>>, GraphError> {
+        Consensus::new(&self.graph, &self.start_indices, &self.labels).compute()
+    }
+
+    /// Heaviest bundle consensus of the sequences
+    pub fn consensus(&self) -> Result<ConsensusPath<T>, GraphError> {
+        Consensus::new(&self.graph, &self.start_indices, &self.labels).heaviest_bundle()
+    }
+
+    /// Thread an aligned sequence through the graph, aligned tokens the
+    /// scorer considers a match share a node
+    pub fn add_alignment<S: Scorer<T> + ?Sized>(&mut self, aln: SeqGraphAlignment<T>, scorer: &S) {
         let seq = aln.seq;
         let seq_match_positions = aln.seq_match_positions;
         let graph_match_node_indices = aln.graph_match_node_indices;
+        let sequence = self.labels.len();
 
         let mut first_node_index = None;
         let mut head_node_index = None;
@@ -69,20 +245,23 @@ impl POAGraph {
             .iter()
             .filter_map(|x| x.as_ref().map(|n| *n))
             .collect();
-        let seq_start_pos = *valid_seq_positions.first().unwrap();
-        let seq_end_pos = *valid_seq_positions.last().unwrap();
-
-        // println!("valid_seq_positions={valid_seq_positions:?}");
-        // println!("seq_start_pos={seq_start_pos:?}");
-        // println!("seq_end_pos={seq_end_pos:?}");
+        // local alignments may leave an unaligned prefix and suffix, when
+        // nothing is aligned the whole sequence is treated as the prefix
+        let seq_start_pos = valid_seq_positions
+            .first()
+            .copied()
+            .unwrap_or(seq.len() as i32);
+        let seq_end_pos = valid_seq_positions
+            .last()
+            .copied()
+            .unwrap_or(seq.len() as i32 - 1);
 
         if seq_start_pos > 0 {
             (first_node_index, head_node_index) =
-                self.add_seq_segment(aln.label.clone(), &seq[0..seq_start_pos as usize]);
+                self.add_seq_segment(sequence, &seq[0..seq_start_pos as usize]);
         }
-        if seq_end_pos < seq.len() as i32 {
-            (tail_node_index, _) =
-                self.add_seq_segment(aln.label.clone(), &seq[seq_end_pos as usize + 1..]);
+        if seq_end_pos + 1 < seq.len() as i32 {
+            (tail_node_index, _) = self.add_seq_segment(sequence, &seq[seq_end_pos as usize + 1..]);
         }
 
         // now we march along the aligned part. For each base, we find or create
@@ -110,7 +289,7 @@ impl POAGraph {
             // this is an aligned position
             if let Some(match_node_index) = match_node_index {
                 // matching alignment
-                if self.graph[match_node_index].item == *seq_item {
+                if scorer.is_match(seq_item, &self.graph[match_node_index].item) {
                     node_index = match_node_index;
                 }
                 // non matching alignment
@@ -118,7 +297,7 @@ impl POAGraph {
                     let other_aligned = &self.graph[match_node_index].aligned_to;
                     let mut found_node = None;
                     for other_node in other_aligned {
-                        if self.graph[*other_node].item == *seq_item {
+                        if scorer.is_match(seq_item, &self.graph[*other_node].item) {
                             found_node = Some(other_node);
                         }
                     }
@@ -130,15 +309,13 @@ impl POAGraph {
                     else {
                         let other_node_indices =
                             [vec![match_node_index].as_slice(), other_aligned.as_slice()].concat();
-                        for other_node_index in other_node_indices.iter() {
-                            self.graph[*other_node_index]
-                                .aligned_to
-                                .push(*other_node_index);
-                        }
                         node_index = self.graph.add_node(NodeData {
                             item: seq_item.clone(),
-                            aligned_to: other_node_indices,
+                            aligned_to: other_node_indices.clone(),
                         });
+                        for other_node_index in other_node_indices.iter() {
+                            self.graph[*other_node_index].aligned_to.push(node_index);
+                        }
                     }
                 }
             }
@@ -152,7 +329,7 @@ impl POAGraph {
 
             // if a new start is there
             if let Some(head_node_index) = head_node_index {
-                self.add_or_update_edge(head_node_index, node_index, aln.label.clone())
+                self.add_or_update_edge(head_node_index, node_index, sequence)
             }
 
             // update head
@@ -166,39 +343,142 @@ impl POAGraph {
 
         // add the edges
         if let (Some(head_node_index), Some(tail_node_index)) = (head_node_index, tail_node_index) {
-            self.add_or_update_edge(head_node_index, tail_node_index, aln.label.clone());
+            self.add_or_update_edge(head_node_index, tail_node_index, sequence);
         }
 
         // record the summaries
-        self.sequeces.push(seq);
+        self.sequences.push(seq);
         self.labels.push(aln.label);
         self.start_indices.push(first_node_index.unwrap());
+        self.paths.push(self.path(sequence));
+    }
+
+    /// Check that the graph is acyclic, that nodes are aligned to each
+    /// other both ways and that each sequence follows a single path from
+    /// its start through all of its tokens
+    pub fn validate(&self) -> Result<(), GraphError> {
+        topological_order(&self.graph)?;
+
+        for node in self.graph.node_indices() {
+            for &other in &self.graph[node].aligned_to {
+                if !self.graph[other].aligned_to.contains(&node) {
+                    return Err(GraphError::AsymmetricAlignment(node, other));
+                }
+            }
+        }
+
+        let mut edge_counts = vec![0; self.labels.len()];
+        for edge in self.graph.edge_weights() {
+            for sequence in edge.sequences.ones() {
+                if let Some(count) = edge_counts.get_mut(sequence) {
+                    *count += 1;
+                }
+            }
+        }
+
+        for (sequence, (label, &start)) in self.labels.iter().zip(&self.start_indices).enumerate() {
+            let carrying = |node: NodeIndex, direction: Direction| {
+                self.graph
+                    .edges_directed(node, direction)
+                    .filter(|edge| edge.weight().contains(sequence))
+                    .collect::<Vec<_>>()
+            };
+            if !carrying(start, Direction::Incoming).is_empty() {
+                return Err(GraphError::PathFork {
+                    label: label.clone(),
+                    node: start,
+                });
+            }
+
+            // the graph is acyclic, so the walk ends
+            let mut path = HashSet::from([start]);
+            let mut node = start;
+            loop {
+                let next = carrying(node, Direction::Outgoing);
+                if next.len() > 1 || carrying(node, Direction::Incoming).len() > 1 {
+                    return Err(GraphError::PathFork {
+                        label: label.clone(),
+                        node,
+                    });
+                }
+                let Some(edge) = next.first() else {
+                    break;
+                };
+                node = edge.target();
+                path.insert(node);
+            }
+
+            // with no forks, any other edge of the sequence is off the path
+            let stray = (edge_counts[sequence] != path.len() - 1)
+                .then(|| {
+                    self.graph.edge_references().find(|edge| {
+                        edge.weight().contains(sequence) && !path.contains(&edge.source())
+                    })
+                })
+                .flatten();
+            if let Some(edge) = stray {
+                return Err(GraphError::PathBreak {
+                    label: label.clone(),
+                    node: edge.source(),
+                });
+            }
+            let (length, expected) = (path.len(), self.sequences[sequence].len());
+            if length != expected {
+                return Err(GraphError::PathLength {
+                    label: label.clone(),
+                    node,
+                    length,
+                    expected,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the sequence as a path of new nodes
+    fn add_unaligned(&mut self, label: String, seq: Vec<T>) -> Result<(), PoaError> {
+        let (Some(first_node_index), _) = self.add_seq_segment(self.labels.len(), &seq) else {
+            return Err(PoaError::EmptyInput {
+                path: String::new(),
+                label: Some(label),
+            });
+        };
+        self.sequences.push(seq);
+        self.labels.push(label);
+        self.start_indices.push(first_node_index);
+        self.paths.push(self.path(self.labels.len() - 1));
+        Ok(())
+    }
+
+    /// Nodes the sequence passes through, following its edges from its start
+    fn path(&self, sequence: SequenceId) -> Vec<NodeIndex> {
+        let mut path = vec![self.start_indices[sequence]];
+        while let Some(edge) = self
+            .graph
+            .edges(path[path.len() - 1])
+            .find(|edge| edge.weight().contains(sequence))
+        {
+            path.push(edge.target());
+        }
+        path
     }
 
-    fn add_or_update_edge(&mut self, a: NodeIndex, b: NodeIndex, label: String) {
+    fn add_or_update_edge(&mut self, a: NodeIndex, b: NodeIndex, sequence: SequenceId) {
         if let Some(edge) = self.graph.find_edge(a, b) {
-            self.graph[edge].labels.push(label);
+            self.graph[edge].insert(sequence);
         } else {
-            self.graph.add_edge(
-                a,
-                b,
-                EdgeData {
-                    labels: vec![label],
-                },
-            );
+            self.graph.add_edge(a, b, EdgeData::new(sequence));
         }
     }
 
     fn add_seq_segment(
         &mut self,
-        label: String,
-        seq: &[String],
+        sequence: SequenceId,
+        seq: &[T],
     ) -> (Option<NodeIndex>, Option<NodeIndex>) {
-        // println!("Adding segment label={label} seq={seq:?}");
         let nodes: Vec<NodeIndex> = seq
             .iter()
             .map(|item| {
-                self.width = max(item.len(), self.width);
                 self.graph.add_node(NodeData {
                     item: item.clone(),
                     aligned_to: vec![],
@@ -208,7 +488,7 @@ impl POAGraph {
 
         for (position, &index) in nodes.iter().enumerate() {
             if position < nodes.len() - 1 {
-                self.add_or_update_edge(index, nodes[position + 1], label.clone());
+                self.add_or_update_edge(index, nodes[position + 1], sequence);
             }
         }
         (nodes.first().copied(), nodes.last().copied())
@@ -218,32 +498,169 @@ impl POAGraph {
 #[cfg(test)]
 mod graph_tests {
     use super::*;
+    use crate::{
+        alignment::AlignmentMode,
+        scoring::{EditDistance, Scoring},
+        test_utils::{graph_of, to_seq},
+    };
 
     #[test]
     fn new_test() {
         let seq = vec!["ABC".to_string(), "BBC".to_string(), "DDD".to_string()];
-        let graph = POAGraph::new("seq_1".to_string(), seq);
+        let graph = POAGraph::new("seq_1".to_string(), seq).unwrap();
         assert_eq!(graph.graph.node_indices().len(), 3);
         assert_eq!(graph.graph.edge_indices().len(), 2);
     }
 
     #[test]
     fn add_aln_test() {
-        let seq1 = vec![
-            "T".to_string(),
-            "G".to_string(),
-            "X".to_string(),
-            "T".to_string(),
-        ];
-        let seq2 = vec![
-            "A".to_string(),
-            "T".to_string(),
-            "G".to_string(),
-            "X".to_string(),
-            "T".to_string(),
+        let graph = graph_of(&[("seq_1", "TGXT"), ("seq_2", "ATGXT")]);
+        // every step of both sequences is recorded once
+        let steps: usize = graph.graph.edge_weights().map(|e| e.weight()).sum();
+        assert_eq!(steps, 3 + 4);
+        assert!(graph.graph.edge_weights().all(|e| e.contains(1)));
+    }
+
+    #[test]
+    fn add_local_aln_test() {
+        let records = [
+            ("seq_1", "XXACGTYY"),
+            ("seq_2", "ZZACGTZZ"),
+            ("seq_3", "QQQ"),
+        ]
+        .map(|(label, seq)| (label.to_string(), to_seq(seq)));
+        let graph = POAGraph::from_records(
+            records.into(),
+            &Scoring::default(),
+            AlignmentMode::Local.into(),
+        )
+        .unwrap();
+        // the unaligned prefix and suffix of seq_2 and all of seq_3 are new nodes
+        assert_eq!(graph.graph.node_count(), 8 + 4 + 3);
+        // seq_2 is threaded through the shared core
+        let seq_2_edges = graph.graph.edge_weights().filter(|e| e.contains(1)).count();
+        assert_eq!(seq_2_edges, 7);
+        assert_eq!(graph.start_indices.len(), 3);
+    }
+
+    #[test]
+    fn add_fuzzy_aln_test() {
+        let words = |s: &str| s.split(' ').map(|c| c.to_string()).collect::<Vec<String>>();
+        let add = |scorer: &EditDistance| {
+            let records = vec![
+                ("seq_1".to_string(), words("ACGT ATTCC ACGT")),
+                ("seq_2".to_string(), words("ACGT ATTCG ACGT")),
+            ];
+            POAGraph::from_records(records, scorer, AlignmentMode::Global.into()).unwrap()
+        };
+
+        // ATTCG is similar enough to reuse the ATTCC node
+        let graph = add(&EditDistance::new(Scoring::default(), 0.8));
+        assert_eq!(graph.graph.node_count(), 3);
+
+        // otherwise it becomes an aligned sibling, linked both ways
+        let graph = add(&EditDistance::new(Scoring::default(), 0.9));
+        assert_eq!(graph.graph.node_count(), 4);
+        let sibling = graph.graph.node_indices().next_back().unwrap();
+        assert_eq!(graph.graph[sibling].item, "ATTCG");
+        for &other in &graph.graph[sibling].aligned_to {
+            assert!(graph.graph[other].aligned_to.contains(&sibling));
+        }
+    }
+
+    #[test]
+    fn validate_test() {
+        let build = || graph_of(&[("seq_1", "ACGT"), ("seq_2", "AGGT")]);
+        let node = NodeIndex::new;
+        assert_eq!(build().validate(), Ok(()));
+
+        let mut graph = build();
+        graph.graph.add_edge(node(3), node(0), EdgeData::new(0));
+        assert!(matches!(graph.validate(), Err(GraphError::Cycle(_))));
+        let aligned = SeqGraphAlignment::align_seq_to_graph(
+            "seq_3".to_string(),
+            to_seq("ACGT"),
+            &graph.graph,
+            &Scoring::default(),
+            AlignmentMode::Global.into(),
+        );
+        assert!(matches!(aligned, Err(GraphError::Cycle(_))));
+
+        // the G of seq_2 is aligned to the C of seq_1
+        let mut graph = build();
+        graph.graph[node(4)].aligned_to.clear();
+        assert_eq!(
+            graph.validate(),
+            Err(GraphError::AsymmetricAlignment(node(1), node(4)))
+        );
+
+        let mut graph = build();
+        graph.add_or_update_edge(node(0), node(2), 0);
+        assert_eq!(
+            graph.validate(),
+            Err(GraphError::PathFork {
+                label: "seq_1".to_string(),
+                node: node(0),
+            })
+        );
+
+        let mut graph = build();
+        let edge = graph.graph.find_edge(node(1), node(2)).unwrap();
+        graph.graph.remove_edge(edge);
+        assert_eq!(
+            graph.validate(),
+            Err(GraphError::PathBreak {
+                label: "seq_1".to_string(),
+                node: node(2),
+            })
+        );
+
+        let mut graph = build();
+        graph.sequences[1].push("A".to_string());
+        assert_eq!(
+            graph.validate(),
+            Err(GraphError::PathLength {
+                label: "seq_2".to_string(),
+                node: node(3),
+                length: 4,
+                expected: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn from_records_test() {
+        let options = AlignmentMode::Global.into();
+        let records = vec![
+            ("seq_1".to_string(), to_seq("TGXT")),
+            ("seq_2".to_string(), to_seq("TGAXT")),
         ];
-        let mut graph = POAGraph::new("seq_1".to_string(), seq1);
-        let sg_aln = SeqGraphAlignment::align_seq_to_graph("seq_2".to_string(), seq2, &graph.graph);
-        graph.add_alignment(sg_aln);
+        let mut graph = POAGraph::from_records(records, &Scoring::default(), options).unwrap();
+        let stats = graph
+            .add_sequence(
+                "seq_3".to_string(),
+                to_seq("TGX"),
+                &Scoring::default(),
+                options,
+            )
+            .unwrap();
+        assert_eq!(stats.unwrap().deletions, 1);
+        assert_eq!(graph.labels, vec!["seq_1", "seq_2", "seq_3"]);
+        assert_eq!(graph.msa().unwrap().len(), 3);
+        assert_eq!(graph.consensus().unwrap().items, to_seq("TGXT"));
+
+        assert!(matches!(
+            POAGraph::<String>::from_records(vec![], &Scoring::default(), options),
+            Err(PoaError::EmptyInput { label: None, .. })
+        ));
+        assert!(matches!(
+            POAGraph::<String>::new("seq_0".to_string(), vec![]),
+            Err(PoaError::EmptyInput { label: Some(_), .. })
+        ));
+        let added = graph.add_sequence("seq_4".to_string(), vec![], &Scoring::default(), options);
+        assert!(matches!(
+            added,
+            Err(PoaError::EmptyInput { label: Some(label), .. }) if label == "seq_4"
+        ));
     }
 }
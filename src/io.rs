@@ -1,42 +1,399 @@
-use crate::graph::{EdgeData, NodeData};
+use crate::{
+    alignment::{AlignmentStats, SeqGraphAlignment},
+    consensus::{ConsensusPath, MsaRow},
+    error::PoaError,
+    graph::{EdgeData, NodeData, POAGraph, Token},
+    scoring::SubstitutionTable,
+};
 use petgraph::{
     dot::{Config, Dot},
-    graph::DiGraph,
-    Graph,
+    graph::{DiGraph, EdgeReference},
 };
 use std::{
-    cmp::min,
+    cmp::{max, min},
+    collections::HashMap,
+    fmt::{Debug, Write as _},
     fs::File,
-    io::{BufWriter, Write},
+    io::{BufRead, BufReader, BufWriter, Write},
 };
 
-fn get_dot(graph: &DiGraph<NodeData, EdgeData>) -> Dot<&Graph<NodeData, EdgeData>> {
-    Dot::with_attr_getters(
-        graph,
+/// How the body of a FASTA record is split into tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tokeniser {
+    /// Every character other than whitespace is a token, so that wrapped
+    /// lines may end in spaces
+    Char,
+    /// Tokens are separated by any whitespace, including line breaks
+    Whitespace,
+    /// Tokens are separated by the given delimiter, line breaks are ignored
+    Delimiter(String),
+}
+
+impl Tokeniser {
+    fn tokenise(&self, lines: &[String]) -> Vec<String> {
+        match self {
+            Tokeniser::Char => lines
+                .iter()
+                .flat_map(|line| {
+                    line.chars()
+                        .filter(|c| !c.is_whitespace())
+                        .map(|c| c.to_string())
+                })
+                .collect(),
+            Tokeniser::Whitespace => lines
+                .iter()
+                .flat_map(|line| line.split_whitespace().map(|s| s.to_string()))
+                .collect(),
+            Tokeniser::Delimiter(delimiter) => lines
+                .concat()
+                .split(delimiter.as_str())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Parse FASTA formatted records, headers become labels and bodies
+/// (which may span several lines) are split using the tokeniser
+pub fn parse_fasta<R: BufRead>(
+    reader: R,
+    tokeniser: &Tokeniser,
+) -> Result<Vec<(String, Vec<String>)>, PoaError> {
+    let mut records = vec![];
+    let mut label: Option<String> = None;
+    let mut lines: Vec<String> = vec![];
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line.map_err(PoaError::io(""))?;
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        // blank lines and old style comments carry no sequence
+        if line.trim().is_empty() || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('>') {
+            if let Some(label) = label.take() {
+                records.push((label, tokeniser.tokenise(&lines)));
+            }
+            label = Some(header.trim().to_string());
+            lines.clear();
+        } else if label.is_some() {
+            lines.push(line.to_string());
+        } else {
+            return Err(PoaError::Parse {
+                path: String::new(),
+                line: line_number + 1,
+                column: 1,
+                message: "FASTA record found without a header".to_string(),
+            });
+        }
+    }
+
+    if let Some(label) = label {
+        records.push((label, tokeniser.tokenise(&lines)));
+    }
+
+    Ok(records)
+}
+
+pub fn read_fasta(
+    path: &str,
+    tokeniser: &Tokeniser,
+) -> Result<Vec<(String, Vec<String>)>, PoaError> {
+    let file = File::open(path).map_err(PoaError::io(path))?;
+    parse_fasta(BufReader::new(file), tokeniser).map_err(|error| error.in_file(path))
+}
+
+/// Parse rows of a label followed by its tokens, separated by `separator`.
+/// Blank lines are skipped and tokens may not be empty.
+pub fn parse_table<R: BufRead>(
+    reader: R,
+    separator: char,
+) -> Result<Vec<(String, Vec<String>)>, PoaError> {
+    let mut records = vec![];
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line.map_err(PoaError::io(""))?;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split(separator);
+        let label = fields.next().unwrap_or_default().to_string();
+        let mut column = label.chars().count() + 2;
+        let mut tokens = vec![];
+        for field in fields {
+            if field.is_empty() {
+                return Err(PoaError::Parse {
+                    path: String::new(),
+                    line: line_number + 1,
+                    column,
+                    message: "empty token".to_string(),
+                });
+            }
+            column += field.chars().count() + 1;
+            tokens.push(field.to_string());
+        }
+        records.push((label, tokens));
+    }
+
+    Ok(records)
+}
+
+pub fn read_table(path: &str, separator: char) -> Result<Vec<(String, Vec<String>)>, PoaError> {
+    let file = File::open(path).map_err(PoaError::io(path))?;
+    parse_table(BufReader::new(file), separator).map_err(|error| error.in_file(path))
+}
+
+/// Read labelled sequences from a CSV, TSV or FASTA file, told apart by
+/// the extension, each with at least one token
+pub fn read_records(
+    path: &str,
+    tokeniser: &Tokeniser,
+) -> Result<Vec<(String, Vec<String>)>, PoaError> {
+    let extension = path.to_lowercase();
+    let records = if extension.ends_with(".csv") {
+        read_table(path, ',')?
+    } else if extension.ends_with(".tsv") {
+        read_table(path, '\t')?
+    } else if extension.ends_with(".fasta") || extension.ends_with(".fa") {
+        read_fasta(path, tokeniser)?
+    } else {
+        return Err(PoaError::Config(format!(
+            "{}: unknown format, expected a .csv, .tsv, .fasta or .fa file",
+            path
+        )));
+    };
+
+    if records.is_empty() {
+        return Err(PoaError::EmptyInput {
+            path: path.to_string(),
+            label: None,
+        });
+    }
+    if let Some((label, _)) = records.iter().find(|(_, seq)| seq.is_empty()) {
+        return Err(PoaError::EmptyInput {
+            path: path.to_string(),
+            label: Some(label.clone()),
+        });
+    }
+    Ok(records)
+}
+
+/// Describe duplicate and empty labels. Sequences are tracked by their
+/// position so these are allowed, but usually point at a problem in the input
+pub fn label_warnings(labels: &[String]) -> Vec<String> {
+    let mut warnings = vec![];
+    let mut positions: HashMap<&str, Vec<usize>> = HashMap::new();
+
+    for (position, label) in labels.iter().enumerate() {
+        if label.is_empty() {
+            warnings.push(format!("Sequence {} has an empty label", position + 1));
+        } else {
+            positions.entry(label).or_default().push(position + 1);
+        }
+    }
+
+    let mut duplicates: Vec<(&str, Vec<usize>)> = positions
+        .into_iter()
+        .filter(|(_, positions)| positions.len() > 1)
+        .collect();
+    duplicates.sort_by_key(|(_, positions)| positions[0]);
+    for (label, positions) in duplicates {
+        let positions: Vec<String> = positions.iter().map(|p| p.to_string()).collect();
+        warnings.push(format!(
+            "Label '{}' is shared by sequences {}",
+            label,
+            positions.join(", ")
+        ));
+    }
+
+    warnings
+}
+
+/// Parse a substitution matrix, either a square table with a header row of
+/// tokens followed by one row of scores per token (BLOSUM style), or sparse
+/// lines of `token_a token_b score`. The first two lines that are not
+/// comments decide which, lines starting with `#` are comments. Tokens may
+/// be numbers. Pairs are symmetric,
+/// a pair given different scores is an error, and pairs that are not listed
+/// score `table.default_score`.
+pub fn parse_substitution_matrix<R: BufRead>(
+    reader: R,
+    mut table: SubstitutionTable,
+) -> Result<SubstitutionTable, PoaError> {
+    // fields of each line with the columns they start at
+    let mut rows: Vec<(usize, Vec<(usize, String)>)> = vec![];
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line.map_err(PoaError::io(""))?;
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let mut fields = vec![];
+        let mut start = None;
+        for (column, (at, c)) in line.char_indices().chain([(line.len(), ' ')]).enumerate() {
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some((column + 1, at)),
+                (true, Some((first, from))) => {
+                    fields.push((first, line[from..at].to_string()));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        rows.push((line_number + 1, fields));
+    }
+
+    let parse_score = |line: usize, (column, score): &(usize, String)| {
+        score.parse::<i32>().map_err(|_| PoaError::Parse {
+            path: String::new(),
+            line,
+            column: *column,
+            message: format!("invalid score '{}'", score),
+        })
+    };
+
+    // pairs are symmetric, so a pair given twice, in either order, must be
+    // given the same score
+    let mut given: HashMap<(String, String), (i32, usize)> = HashMap::new();
+    let mut insert = |line: usize, a: &String, b: &String, score: &(usize, String)| {
+        let value = parse_score(line, score)?;
+        let pair = if a <= b {
+            (a.clone(), b.clone())
+        } else {
+            (b.clone(), a.clone())
+        };
+        match given.get(&pair) {
+            Some(&(other, other_line)) if other != value => {
+                return Err(PoaError::Parse {
+                    path: String::new(),
+                    line,
+                    column: score.0,
+                    message: format!(
+                        "score {} of {} {} differs from {} on line {}",
+                        value, a, b, other, other_line
+                    ),
+                })
+            }
+            _ => given.insert(pair, (value, line)),
+        };
+        table.insert(a.clone(), b.clone(), value);
+        Ok(())
+    };
+
+    // sparse lines end with a score, a square table starts with a header of
+    // tokens, which may be numbers, followed by a row of one more field
+    // giving scores after its token
+    let is_score = |line: usize, field: &(usize, String)| parse_score(line, field).is_ok();
+    let sparse = match rows.as_slice() {
+        [] => false,
+        [(line_number, header), rest @ ..] => {
+            let square = rest.first().is_some_and(|(row_number, fields)| {
+                fields.len() == header.len() + 1
+                    && fields[1..].iter().all(|field| is_score(*row_number, field))
+            });
+            !square && header.len() == 3 && is_score(*line_number, &header[2])
+        }
+    };
+
+    if sparse {
+        for (line_number, fields) in &rows {
+            if fields.len() != 3 {
+                // at the first extra field, or the last one given
+                let at = fields.get(3).or(fields.last());
+                return Err(PoaError::Parse {
+                    path: String::new(),
+                    line: *line_number,
+                    column: at.map_or(1, |field| field.0),
+                    message: format!(
+                        "expected token_a token_b score, found {} fields",
+                        fields.len()
+                    ),
+                });
+            }
+            insert(*line_number, &fields[0].1, &fields[1].1, &fields[2])?;
+        }
+    } else if let Some(((_, header), rows)) = rows.split_first() {
+        for (line_number, fields) in rows {
+            if fields.len() != header.len() + 1 {
+                // at the first extra score, or the last one given
+                let at = fields.get(header.len() + 1).or(fields.last());
+                return Err(PoaError::Parse {
+                    path: String::new(),
+                    line: *line_number,
+                    column: at.map_or(1, |field| field.0),
+                    message: format!(
+                        "expected {} scores, found {}",
+                        header.len(),
+                        fields.len().saturating_sub(1)
+                    ),
+                });
+            }
+            for (token, score) in header.iter().zip(&fields[1..]) {
+                insert(*line_number, &fields[0].1, &token.1, score)?;
+            }
+        }
+    }
+
+    Ok(table)
+}
+
+pub fn read_substitution_matrix(
+    path: &str,
+    table: SubstitutionTable,
+) -> Result<SubstitutionTable, PoaError> {
+    let file = File::open(path).map_err(PoaError::io(path))?;
+    parse_substitution_matrix(BufReader::new(file), table).map_err(|error| error.in_file(path))
+}
+
+/// Render the graph in DOT format, tokens are rendered with `render`
+pub fn get_dot<T: Debug, F: Fn(&T) -> String>(poa: &POAGraph<T>, render: F) -> String {
+    let edge_attrs = |_, e: EdgeReference<EdgeData>| {
+        format!(
+            "label=\"Fragments: [{}]\" penwidth={1} minlen={1}",
+            e.weight()
+                .sequences
+                .ones()
+                .map(|sequence| poa.labels[sequence].as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            min(10, e.weight().weight())
+        )
+    };
+    let node_attrs = |_, e: (_, &NodeData<T>)| format!("label = \"{}\"", render(&e.1.item));
+    let dot = Dot::with_attr_getters(
+        &poa.graph,
         &[Config::EdgeNoLabel, Config::NodeNoLabel],
-        &|_, e| {
-            format!(
-                "label=\"Fragments: [{}]\" penwidth={1} minlen={1}",
-                e.weight().labels.join(", "),
-                min(10, e.weight().labels.len())
-            )
-        },
-        &|_, e| format!("label = \"{}\"", e.1.item),
-    )
+        &edge_attrs,
+        &node_attrs,
+    );
+    format!("{:?}", dot)
 }
 
-pub fn write_dot(graph: &DiGraph<NodeData, EdgeData>, path: &str) -> Result<(), String> {
-    let dot = get_dot(graph);
-    let file = File::create(format!("{}.graph.dot", path))
-        .map_err(|_| format!("Unable to create file: {}", path))?;
+pub fn write_dot<T: Debug, F: Fn(&T) -> String>(
+    poa: &POAGraph<T>,
+    render: F,
+    path: &str,
+) -> Result<(), PoaError> {
+    let dot = get_dot(poa, render);
+    let path = format!("{}.graph.dot", path);
+    let file = File::create(&path).map_err(PoaError::io(&path))?;
     let mut writer = BufWriter::new(file);
     writer
-        .write_all(format!("{:?}", dot).as_bytes())
-        .map_err(|_| "IO Error".to_string())
+        .write_all(dot.as_bytes())
+        .map_err(PoaError::io(&path))
 }
 
-pub fn write_html(graph: &DiGraph<NodeData, EdgeData>, path: &str) -> Result<(), String> {
-    let dot = get_dot(graph);
+pub fn write_html<T: Debug, F: Fn(&T) -> String>(
+    poa: &POAGraph<T>,
+    render: F,
+    path: &str,
+) -> Result<(), PoaError> {
+    let dot = get_dot(poa, render);
     let html = format!(
         r#"<!DOCTYPE html>
 <html lang="en">
@@ -54,7 +411,7 @@ pub fn write_html(graph: &DiGraph<NodeData, EdgeData>, path: &str) -> Result<(),
 
     <script type="text/javascript">
     var container = document.getElementById("mynetwork");
-    var dot = `{:?}`;
+    var dot = `{}`;
     var data = vis.parseDOTNetwork(dot);
     var network = new vis.Network(container, data);
     </script>
@@ -63,10 +420,288 @@ pub fn write_html(graph: &DiGraph<NodeData, EdgeData>, path: &str) -> Result<(),
 "#,
         dot
     );
-    let file = File::create(format!("{}.graph.html", path))
-        .map_err(|_| format!("Unable to create file: {}", path))?;
+    let path = format!("{}.graph.html", path);
+    let file = File::create(&path).map_err(PoaError::io(&path))?;
     let mut writer = BufWriter::new(file);
     writer
         .write_all(html.as_bytes())
-        .map_err(|_| "IO Error".to_string())
+        .map_err(PoaError::io(&path))
+}
+
+/// Write the rows of the multiple sequence alignment, tokens are rendered
+/// with `render` and centred in columns wide enough for the longest, gaps
+/// are `-`
+pub fn write_msa<T, F: Fn(&T) -> String>(
+    rows: &[MsaRow<T>],
+    render: F,
+    path: &str,
+) -> Result<(), PoaError> {
+    let rows: Vec<(&String, Vec<String>)> = rows
+        .iter()
+        .map(|(label, row)| {
+            let items = row
+                .iter()
+                .map(|item| item.as_ref().map_or_else(|| String::from("-"), &render))
+                .collect();
+            (label, items)
+        })
+        .collect();
+    let item_width = rows
+        .iter()
+        .flat_map(|(_, items)| items.iter().map(|item| item.len()))
+        .fold(0, max)
+        + 2;
+    let label_width = rows
+        .iter()
+        .fold(0, |width, (label, _)| max(width, label.len()));
+
+    let file = File::create(path).map_err(PoaError::io(path))?;
+    let mut writer = BufWriter::new(file);
+    for (label, items) in rows {
+        let padded_seq = items.iter().fold(String::new(), |mut output, item| {
+            let _ = write!(output, "{item:^item_width$}");
+            output
+        });
+        writer
+            .write_all(format!("{label:^label_width$} {padded_seq}\n").as_bytes())
+            .map_err(PoaError::io(path))?;
+    }
+    Ok(())
+}
+
+/// Write the consensus tokens, rendered with `render`, and their support as
+/// two TSV rows
+pub fn write_consensus<T, F: Fn(&T) -> String>(
+    consensus: &ConsensusPath<T>,
+    render: F,
+    path: &str,
+) -> Result<(), PoaError> {
+    let path = format!("{}.consensus.tsv", path);
+    let file = File::create(&path).map_err(PoaError::io(&path))?;
+    let mut writer = BufWriter::new(file);
+    let items: Vec<String> = consensus.items.iter().map(render).collect();
+    let support: Vec<String> = consensus.support.iter().map(|s| s.to_string()).collect();
+    writer
+        .write_all(
+            format!(
+                "consensus\t{}\nsupport\t{}\n",
+                items.join("\t"),
+                support.join("\t")
+            )
+            .as_bytes(),
+        )
+        .map_err(PoaError::io(&path))
+}
+
+/// Write each alignment as a row of the sequence, labelled with its score,
+/// over a row of the graph nodes it aligned to
+pub fn write_alignments<T: Token, F: Fn(&T) -> String>(
+    alignments: &[SeqGraphAlignment<T>],
+    graph: &DiGraph<NodeData<T>, EdgeData>,
+    render: F,
+    path: &str,
+) -> Result<(), PoaError> {
+    let path = format!("{}.queries.tsv", path);
+    let file = File::create(&path).map_err(PoaError::io(&path))?;
+    let mut writer = BufWriter::new(file);
+    for aln in alignments {
+        let (_, seq, _, nodes) = aln.get_string(graph, &render);
+        writer
+            .write_all(
+                format!(
+                    "{}\t{}\t{}\ngraph\t\t{}\n",
+                    aln.label,
+                    aln.score(),
+                    seq.join("\t"),
+                    nodes.join("\t")
+                )
+                .as_bytes(),
+            )
+            .map_err(PoaError::io(&path))?;
+    }
+    Ok(())
+}
+
+/// Write the score and counts of aligned pairs of each sequence, None for
+/// the sequence the graph started from
+pub fn write_stats(stats: &[(String, Option<AlignmentStats>)], path: &str) -> Result<(), PoaError> {
+    let path = format!("{}.stats.tsv", path);
+    let file = File::create(&path).map_err(PoaError::io(&path))?;
+    let mut writer = BufWriter::new(file);
+    writer
+        .write_all(b"label\tscore\tmatches\tmismatches\tinsertions\tdeletions\n")
+        .map_err(PoaError::io(&path))?;
+    for (label, stats) in stats {
+        let row = match stats {
+            Some(stats) => format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\n",
+                label,
+                stats.score,
+                stats.matches,
+                stats.mismatches,
+                stats.insertions,
+                stats.deletions
+            ),
+            None => format!("{}\t-\t-\t-\t-\t-\n", label),
+        };
+        writer
+            .write_all(row.as_bytes())
+            .map_err(PoaError::io(&path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod io_tests {
+    use super::{label_warnings, parse_fasta, parse_substitution_matrix, parse_table, Tokeniser};
+    use crate::{
+        error::PoaError,
+        scoring::{Scorer, SubstitutionTable},
+    };
+
+    const FASTA: &str = ">seq_1\nACGT\nAC\n\n>seq_2 second record\nAC GT\nTT\n";
+
+    #[test]
+    fn parse_fasta_char_test() {
+        let records = parse_fasta(FASTA.as_bytes(), &Tokeniser::Char).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].0, "seq_1");
+        assert_eq!(records[0].1, vec!["A", "C", "G", "T", "A", "C"]);
+        assert_eq!(records[1].0, "seq_2 second record");
+        // whitespace is not a token
+        assert_eq!(records[1].1, vec!["A", "C", "G", "T", "T", "T"]);
+    }
+
+    #[test]
+    fn parse_fasta_tokeniser_test() {
+        let records = parse_fasta(FASTA.as_bytes(), &Tokeniser::Whitespace).unwrap();
+        assert_eq!(records[0].1, vec!["ACGT", "AC"]);
+        assert_eq!(records[1].1, vec!["AC", "GT", "TT"]);
+
+        let records = parse_fasta(
+            ">road\nA1;M4;\nA1;B2\n".as_bytes(),
+            &Tokeniser::Delimiter(";".to_string()),
+        )
+        .unwrap();
+        assert_eq!(records[0].1, vec!["A1", "M4", "A1", "B2"]);
+    }
+
+    #[test]
+    fn parse_fasta_missing_header_test() {
+        assert!(parse_fasta("ACGT\n>seq_1\nACGT\n".as_bytes(), &Tokeniser::Char).is_err());
+    }
+
+    #[test]
+    fn label_warnings_test() {
+        let labels = ["a", "b", "", "a", "b", "a"].map(|s| s.to_string());
+        assert_eq!(
+            label_warnings(&labels),
+            vec![
+                "Sequence 3 has an empty label",
+                "Label 'a' is shared by sequences 1, 4, 6",
+                "Label 'b' is shared by sequences 2, 5",
+            ]
+        );
+        assert!(label_warnings(&["a".to_string()]).is_empty());
+    }
+
+    #[test]
+    fn parse_substitution_matrix_test() {
+        let square =
+            "# road classes\n    A1  A2  M1\nA1   5   2  -9\nA2   2   5  -9\nM1  -9  -9   5\n";
+        let table = parse_substitution_matrix(square.as_bytes(), SubstitutionTable::new(-1, 0, -2))
+            .unwrap();
+        let score = |a: &str, b: &str| table.score(&a.to_string(), &b.to_string());
+        assert_eq!(score("A1", "A1"), 5);
+        assert_eq!(score("A2", "A1"), 2);
+        assert_eq!(score("M1", "A2"), -9);
+        assert_eq!(score("B1", "A1"), -1);
+
+        let sparse = "A1\tA1\t5\nA1\tA2\t2\n";
+        let table = parse_substitution_matrix(sparse.as_bytes(), SubstitutionTable::new(-1, 0, -2))
+            .unwrap();
+        let score = |a: &str, b: &str| table.score(&a.to_string(), &b.to_string());
+        assert_eq!(score("A2", "A1"), 2);
+        assert_eq!(score("A2", "A2"), -1);
+
+        let ragged = "    A1  A2\nA1   5\n";
+        assert!(matches!(
+            parse_substitution_matrix(ragged.as_bytes(), SubstitutionTable::new(-1, 0, -2)),
+            Err(PoaError::Parse {
+                line: 2,
+                column: 6,
+                ..
+            })
+        ));
+        let invalid = "A1 A1 5\nA1 A2 x2\n";
+        assert!(matches!(
+            parse_substitution_matrix(invalid.as_bytes(), SubstitutionTable::new(-1, 0, -2)),
+            Err(PoaError::Parse {
+                line: 2,
+                column: 7,
+                ..
+            })
+        ));
+
+        // a malformed sparse line is reported as such, not as a square table
+        let error = parse_substitution_matrix(
+            "A1 A1 5\nA1 A2 2 3\n".as_bytes(),
+            SubstitutionTable::new(-1, 0, -2),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "2:9: expected token_a token_b score, found 4 fields"
+        );
+
+        // pairs are symmetric, so an asymmetric table is rejected
+        let asymmetric = "    A1  A2\nA1   5   2\nA2   3   5\n";
+        let error =
+            parse_substitution_matrix(asymmetric.as_bytes(), SubstitutionTable::new(-1, 0, -2))
+                .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "3:6: score 3 of A2 A1 differs from 2 on line 2"
+        );
+        // numeric tokens, a header of three is not a sparse line
+        let numeric = "    1   2   3\n1   5   2  -9\n2   2   5  -9\n3  -9  -9   5\n";
+        let table =
+            parse_substitution_matrix(numeric.as_bytes(), SubstitutionTable::new(-1, 0, -2))
+                .unwrap();
+        let score = |a: &str, b: &str| table.score(&a.to_string(), &b.to_string());
+        assert_eq!(score("1", "2"), 2);
+        assert_eq!(score("3", "1"), -9);
+        assert_eq!(score("3", "3"), 5);
+
+        let conflicting = "A1 A2 2\nA2 A1 2\nA2 A1 4\n";
+        assert!(matches!(
+            parse_substitution_matrix(conflicting.as_bytes(), SubstitutionTable::new(-1, 0, -2)),
+            Err(PoaError::Parse {
+                line: 3,
+                column: 7,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_table_test() {
+        let records = parse_table("seq_1,AC,GT\r\n\nseq_2,TT\n".as_bytes(), ',').unwrap();
+        assert_eq!(
+            records,
+            vec![
+                (
+                    "seq_1".to_string(),
+                    vec!["AC".to_string(), "GT".to_string()]
+                ),
+                ("seq_2".to_string(), vec!["TT".to_string()]),
+            ]
+        );
+
+        let error = parse_table("seq_1\tA\nseq_2\tA\t\tC\n".as_bytes(), '\t')
+            .unwrap_err()
+            .in_file("entries.tsv");
+        assert_eq!(error.to_string(), "entries.tsv:2:9: empty token");
+        assert_eq!(error.exit_code(), 65);
+    }
 }
@@ -0,0 +1,45 @@
+//! Partial order alignment of sequences of arbitrary tokens.
+//!
+//! Sequences are aligned one at a time to a graph of the sequences added
+//! before them. The graph then gives the multiple sequence alignment and a
+//! consensus of all of them.
+//!
+//! ```
+//! use generic_poa::{AlignmentMode, PoaAligner, TieBreak};
+//!
+//! let to_seq = |s: &str| s.chars().map(|c| c.to_string()).collect::<Vec<String>>();
+//! let mut aligner = PoaAligner::builder()
+//!     .mode(AlignmentMode::Global)
+//!     .tie_break(TieBreak::RightGaps)
+//!     .build();
+//! aligner.add("seq_1", to_seq("ACGT"))?;
+//! aligner.add("seq_2", to_seq("AGT"))?;
+//! aligner.add("seq_3", to_seq("ACGT"))?;
+//!
+//! assert_eq!(aligner.consensus()?.items, to_seq("ACGT"));
+//! assert_eq!(aligner.msa()?[1].1, vec![Some("A".to_string()), None, Some("G".to_string()), Some("T".to_string())]);
+//! # Ok::<(), generic_poa::PoaError>(())
+//! ```
+
+pub mod aligner;
+pub mod alignment;
+mod anchors;
+pub mod consensus;
+pub mod error;
+pub mod graph;
+pub mod io;
+pub mod scoring;
+mod simd;
+pub mod symbols;
+#[cfg(test)]
+mod test_utils;
+
+pub use aligner::{PoaAligner, PoaAlignerBuilder, SequenceOrder};
+pub use alignment::{
+    AlignmentMode, AlignmentOptions, AlignmentStats, Band, SeqGraphAlignment, TieBreak,
+};
+pub use consensus::{Consensus, ConsensusPath, MsaRow};
+pub use error::PoaError;
+pub use graph::{GraphError, POAGraph, Token};
+pub use scoring::{CaseInsensitive, EditDistance, Scorer, Scoring, SubstitutionTable};
+pub use symbols::{Interned, SymbolTable};
@@ -1,106 +1,195 @@
-use alignment::SeqGraphAlignment;
+use args::Tokenise;
 use clap::Parser;
-use consensus::Consensus;
-use graph::POAGraph;
-use io::{write_dot, write_html};
-use std::{
-    cmp::max,
-    fmt::Write,
-    fs::File,
-    io::{BufRead, BufReader, BufWriter, Write as BufWrite},
+use generic_poa::{
+    io::{
+        label_warnings, read_records, read_substitution_matrix, write_alignments, write_consensus,
+        write_dot, write_html, write_msa, write_stats,
+    },
+    AlignmentOptions, Band, CaseInsensitive, EditDistance, Interned, POAGraph, PoaAligner,
+    PoaError, Scorer, Scoring, SeqGraphAlignment, SubstitutionTable, SymbolTable,
 };
-mod alignment;
+use std::{fmt::Write, process::ExitCode};
 mod args;
-mod consensus;
-mod graph;
-mod io;
 
-fn get_format(path: &str) -> char {
-    if path.to_lowercase().ends_with(".csv") {
-        ','
-    } else if path.to_lowercase().ends_with(".tsv") {
-        '\t'
-    } else if path.to_lowercase().ends_with(".fasta") {
-        '0'
-    } else {
-        '1'
+/// Print an alignment with its score for --debug
+fn print_alignment(
+    aln: &SeqGraphAlignment<u32>,
+    poa: &POAGraph<u32>,
+    render: impl Fn(&u32) -> String,
+) {
+    let (width, s, m, g) = aln.get_string(&poa.graph, render);
+    let width = width + 2;
+    println!(
+        "Graph    : {}",
+        g.iter().fold(String::new(), |mut out, s| {
+            let _ = write!(out, "{s:^width$}");
+            out
+        })
+    );
+    println!(
+        "Match    : {}",
+        m.iter().fold(String::new(), |mut out, s| {
+            let _ = write!(out, "{s:^width$}");
+            out
+        })
+    );
+    println!(
+        "Alignment: {}",
+        s.iter().fold(String::new(), |mut out, s| {
+            let _ = write!(out, "{s:^width$}");
+            out
+        })
+    );
+    println!(
+        "Score    : {} ({} matches, {} mismatches, {} insertions, {} deletions)\n",
+        aln.score(),
+        aln.matches(),
+        aln.mismatches(),
+        aln.insertions(),
+        aln.deletions()
+    );
+}
+
+fn main() -> ExitCode {
+    match run(&args::POACli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::from(error.exit_code())
+        }
     }
 }
 
-fn main() -> Result<(), String> {
-    let args = args::POACli::parse();
-    let mut records = vec![];
-    let sep = get_format(&args.input);
-    match sep {
-        ',' | '\t' => {
-            let file = File::open(&args.input)
-                .map_err(|_| format!("Unable to open file: {}", &args.input))?;
-            let reader = BufReader::new(file);
-            for line in reader.lines() {
-                let line = line.map_err(|_| "IO Error".to_string())?;
-                let chunks: Vec<String> = line.split(sep).map(|s| s.to_string()).collect();
-                records.push((chunks[0].clone(), chunks[1..].to_vec()));
-            }
+fn run(args: &args::POACli) -> Result<(), PoaError> {
+    if args
+        .fuzzy
+        .is_some_and(|threshold| !(0.0..=1.0).contains(&threshold))
+    {
+        return Err(PoaError::Config(
+            "--fuzzy threshold must be between 0 and 1".to_string(),
+        ));
+    }
+    if matches!(args.tokenise, Tokenise::Delimiter) && args.delimiter.is_empty() {
+        return Err(PoaError::Config("--delimiter may not be empty".to_string()));
+    }
+
+    let tokeniser = args.tokeniser();
+    let records = read_records(&args.input, &tokeniser)?;
+    let queries = match &args.queries {
+        Some(path) => read_records(path, &tokeniser)?,
+        None => vec![],
+    };
+
+    if args.warn_labels {
+        let labels: Vec<String> = records.iter().map(|(label, _)| label.clone()).collect();
+        for warning in label_warnings(&labels) {
+            eprintln!("Warning: {}", warning);
         }
-        _ => {}
     }
 
-    let mut poa = POAGraph::new(records[0].0.clone(), records[0].1.clone());
+    // tokens are interned once so that alignment only compares integers
+    let mut symbols = SymbolTable::new();
+    let records: Vec<(String, Vec<u32>)> = records
+        .into_iter()
+        .map(|(label, seq)| {
+            let seq = symbols.intern_seq(&seq);
+            (label, seq)
+        })
+        .collect();
+    let queries: Vec<(String, Vec<u32>)> = queries
+        .into_iter()
+        .map(|(label, seq)| {
+            let seq = symbols.intern_seq(&seq);
+            (label, seq)
+        })
+        .collect();
+    let render = |id: &u32| symbols[*id].clone();
+
+    let scoring = Scoring::new(
+        args.match_score,
+        args.mismatch_score,
+        args.gap_open,
+        args.gap,
+    );
+    let scorer: Box<dyn Scorer<u32> + Sync> = if let Some(matrix) = &args.matrix {
+        let table = read_substitution_matrix(
+            matrix,
+            SubstitutionTable::new(args.mismatch_score, args.gap_open, args.gap),
+        )?;
+        Box::new(Interned::new(table, &symbols))
+    } else if let Some(threshold) = args.fuzzy {
+        Box::new(Interned::new(
+            EditDistance::new(scoring, threshold),
+            &symbols,
+        ))
+    } else if args.ignore_case {
+        Box::new(Interned::new(CaseInsensitive(scoring), &symbols))
+    } else {
+        Box::new(scoring)
+    };
+    let band = match args.band {
+        Some(width) if args.adaptive_band => Band::Adaptive(width),
+        Some(width) => Band::Fixed(width),
+        None => Band::Full,
+    };
+    let options = AlignmentOptions {
+        mode: args.mode.into(),
+        band,
+        max_memory: args.max_memory,
+        anchor: args.anchor,
+        tie_break: args.tie_break.into(),
+    };
+    let mut aligner = PoaAligner::builder()
+        .scorer(scorer)
+        .options(options)
+        .build();
+    let mut stats = vec![];
+    for (label, seq) in records {
+        let added = aligner.add_with(label.clone(), seq, |aln, aligner| {
+            if args.debug {
+                print_alignment(aln, aligner.graph(), render);
+                if let Some(count) = args.co_optimal {
+                    let alternatives =
+                        aligner.co_optimal(aln.label.clone(), aln.seq.clone(), count)?;
+                    println!("Co-optimal alignments: {}\n", alternatives.len());
+                    for alternative in &alternatives {
+                        print_alignment(alternative, aligner.graph(), render);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        stats.push((label, added));
+    }
+    let poa = aligner.graph();
+    poa.validate()?;
 
-    for (label, seq) in &records[1..] {
-        let aln = SeqGraphAlignment::align_seq_to_graph(label.clone(), seq.clone(), &poa.graph);
-        if args.debug {
-            let (width, s, m, g) = aln.get_string(&poa.graph);
-            let width = width + 2;
-            println!(
-                "Graph    : {}",
-                g.iter().fold(String::new(), |mut out, s| {
-                    let _ = write!(out, "{s:^width$}");
-                    out
-                })
-            );
-            println!(
-                "Match    : {}",
-                m.iter().fold(String::new(), |mut out, s| {
-                    let _ = write!(out, "{s:^width$}");
-                    out
-                })
-            );
-            println!(
-                "Alignment: {}\n",
-                s.iter().fold(String::new(), |mut out, s| {
-                    let _ = write!(out, "{s:^width$}");
-                    out
-                })
-            );
+    if !queries.is_empty() {
+        if let Some(threads) = args.threads {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build_global()
+                .map_err(|error| PoaError::Config(format!("--threads: {}", error)))?;
         }
-        poa.add_alignment(aln);
+        let alignments = aligner.align_batch(queries)?;
+        write_alignments(&alignments, &poa.graph, render, &args.output)?;
+    }
+
+    if args.stats {
+        write_stats(&stats, &args.output)?;
     }
 
     if args.graph {
-        write_dot(&poa.graph, &args.output)?;
+        write_dot(poa, render, &args.output)?;
     }
 
     if args.html {
-        write_html(&poa.graph, &args.output)?;
+        write_html(poa, render, &args.output)?;
     }
 
-    let item_width = poa.width + 2;
-    let label_width = poa.labels.iter().rfold(0usize, |acc, b| max(acc, b.len()));
-    let con = Consensus::new(poa.graph, poa.start_indices, poa.labels);
-    let file = File::create(&args.output)
-        .map_err(|_| format!("Unable to create file: {}", &args.output))?;
-    let mut writer = BufWriter::new(file);
-
-    for (label, seq) in con.compute() {
-        let padded_seq: String = seq.into_iter().fold(String::new(), |mut output, item| {
-            let _ = write!(output, "{item:^item_width$}");
-            output
-        });
-        writer
-            .write_all(format!("{label:^label_width$} {padded_seq}\n").as_bytes())
-            .map_err(|_| "IO Error".to_string())?;
+    if args.consensus {
+        write_consensus(&poa.consensus()?, render, &args.output)?;
     }
 
-    Ok(())
+    write_msa(&poa.msa()?, render, &args.output)
 }
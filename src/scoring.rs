@@ -0,0 +1,292 @@
+use crate::graph::Token;
+use std::{cmp::min, collections::HashMap};
+
+/// Scores used when aligning a sequence to the graph. Implement this to
+/// supply a domain specific similarity between tokens.
+///
+/// A gap of length `k` scores `gap_open() + k * gap_extend()`.
+pub trait Scorer<T: PartialEq = String> {
+    /// Score of aligning the sequence token `a` to the graph token `b`
+    fn score(&self, a: &T, b: &T) -> i32;
+
+    /// Additional score for opening a gap, zero for linear gap penalties
+    fn gap_open(&self) -> i32;
+
+    /// Score for each gap position
+    fn gap_extend(&self) -> i32;
+
+    /// Whether the tokens are close enough to share a node in the graph,
+    /// otherwise an aligned pair of tokens becomes two aligned nodes
+    fn is_match(&self, a: &T, b: &T) -> bool {
+        a == b
+    }
+}
+
+impl<T: PartialEq, S: Scorer<T> + ?Sized> Scorer<T> for &S {
+    fn score(&self, a: &T, b: &T) -> i32 {
+        (**self).score(a, b)
+    }
+
+    fn gap_open(&self) -> i32 {
+        (**self).gap_open()
+    }
+
+    fn gap_extend(&self) -> i32 {
+        (**self).gap_extend()
+    }
+
+    fn is_match(&self, a: &T, b: &T) -> bool {
+        (**self).is_match(a, b)
+    }
+}
+
+impl<T: PartialEq, S: Scorer<T> + ?Sized> Scorer<T> for Box<S> {
+    fn score(&self, a: &T, b: &T) -> i32 {
+        (**self).score(a, b)
+    }
+
+    fn gap_open(&self) -> i32 {
+        (**self).gap_open()
+    }
+
+    fn gap_extend(&self) -> i32 {
+        (**self).gap_extend()
+    }
+
+    fn is_match(&self, a: &T, b: &T) -> bool {
+        (**self).is_match(a, b)
+    }
+}
+
+/// Exact matching of tokens with fixed match and mismatch scores
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scoring {
+    pub match_score: i32,
+    pub mismatch_score: i32,
+    pub gap_open: i32,
+    pub gap_extend: i32,
+}
+
+impl Default for Scoring {
+    fn default() -> Self {
+        Self {
+            match_score: 1,
+            mismatch_score: -1,
+            gap_open: 0,
+            gap_extend: -2,
+        }
+    }
+}
+
+impl Scoring {
+    pub fn new(match_score: i32, mismatch_score: i32, gap_open: i32, gap_extend: i32) -> Self {
+        Self {
+            match_score,
+            mismatch_score,
+            gap_open,
+            gap_extend,
+        }
+    }
+}
+
+impl<T: PartialEq> Scorer<T> for Scoring {
+    fn score(&self, a: &T, b: &T) -> i32 {
+        if a == b {
+            self.match_score
+        } else {
+            self.mismatch_score
+        }
+    }
+
+    fn gap_open(&self) -> i32 {
+        self.gap_open
+    }
+
+    fn gap_extend(&self) -> i32 {
+        self.gap_extend
+    }
+}
+
+/// Matching of tokens ignoring case, otherwise scores as `Scoring`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CaseInsensitive(pub Scoring);
+
+impl Scorer for CaseInsensitive {
+    fn score(&self, a: &String, b: &String) -> i32 {
+        if self.is_match(a, b) {
+            self.0.match_score
+        } else {
+            self.0.mismatch_score
+        }
+    }
+
+    fn gap_open(&self) -> i32 {
+        self.0.gap_open
+    }
+
+    fn gap_extend(&self) -> i32 {
+        self.0.gap_extend
+    }
+
+    fn is_match(&self, a: &String, b: &String) -> bool {
+        a == b || a.to_lowercase() == b.to_lowercase()
+    }
+}
+
+/// Partial matching of tokens by their normalised Levenshtein similarity,
+/// `1 - distance / length of the longer token`. Similar tokens score
+/// between the mismatch and the match score of `scoring`, tokens less
+/// similar than `threshold` are mismatches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EditDistance {
+    pub scoring: Scoring,
+    pub threshold: f64,
+}
+
+impl EditDistance {
+    pub fn new(scoring: Scoring, threshold: f64) -> Self {
+        Self { scoring, threshold }
+    }
+
+    /// Normalised similarity of the tokens in `[0, 1]`
+    pub fn similarity(a: &str, b: &str) -> f64 {
+        let longest = a.chars().count().max(b.chars().count());
+        if longest == 0 {
+            return 1.0;
+        }
+        1.0 - levenshtein(a, b) as f64 / longest as f64
+    }
+}
+
+impl Scorer for EditDistance {
+    fn score(&self, a: &String, b: &String) -> i32 {
+        let similarity = EditDistance::similarity(a, b);
+        if similarity < self.threshold {
+            return self.scoring.mismatch_score;
+        }
+        let range = (self.scoring.match_score - self.scoring.mismatch_score) as f64;
+        self.scoring.mismatch_score + (range * similarity).round() as i32
+    }
+
+    fn gap_open(&self) -> i32 {
+        self.scoring.gap_open
+    }
+
+    fn gap_extend(&self) -> i32 {
+        self.scoring.gap_extend
+    }
+
+    fn is_match(&self, a: &String, b: &String) -> bool {
+        EditDistance::similarity(a, b) >= self.threshold
+    }
+}
+
+/// Number of character insertions, deletions and substitutions turning
+/// `a` into `b`
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = min(substitution, min(previous[j + 1], current[j]) + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Scores looked up from a table of token pairs, pairs are symmetric and
+/// those not in the table score `default_score`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubstitutionTable<T: Token = String> {
+    scores: HashMap<T, HashMap<T, i32>>,
+    pub default_score: i32,
+    pub gap_open: i32,
+    pub gap_extend: i32,
+}
+
+impl<T: Token> SubstitutionTable<T> {
+    pub fn new(default_score: i32, gap_open: i32, gap_extend: i32) -> Self {
+        Self {
+            scores: HashMap::new(),
+            default_score,
+            gap_open,
+            gap_extend,
+        }
+    }
+
+    /// Set the score of aligning `a` and `b` in either order
+    pub fn insert(&mut self, a: T, b: T, score: i32) {
+        self.scores
+            .entry(a.clone())
+            .or_default()
+            .insert(b.clone(), score);
+        self.scores.entry(b).or_default().insert(a, score);
+    }
+}
+
+impl<T: Token> Scorer<T> for SubstitutionTable<T> {
+    fn score(&self, a: &T, b: &T) -> i32 {
+        self.scores
+            .get(a)
+            .and_then(|scores| scores.get(b))
+            .copied()
+            .unwrap_or(self.default_score)
+    }
+
+    fn gap_open(&self) -> i32 {
+        self.gap_open
+    }
+
+    fn gap_extend(&self) -> i32 {
+        self.gap_extend
+    }
+}
+
+#[cfg(test)]
+mod scoring_tests {
+    use super::*;
+
+    fn score<S: Scorer>(scorer: &S, a: &str, b: &str) -> i32 {
+        scorer.score(&a.to_string(), &b.to_string())
+    }
+
+    #[test]
+    fn scorers_test() {
+        let scoring = Scoring::default();
+        assert_eq!(score(&scoring, "road", "road"), 1);
+        assert_eq!(score(&scoring, "road", "Road"), -1);
+
+        let scorer = CaseInsensitive(scoring);
+        assert_eq!(score(&scorer, "road", "Road"), 1);
+        assert_eq!(score(&scorer, "road", "lane"), -1);
+        assert_eq!(scorer.gap_extend(), -2);
+
+        let mut table = SubstitutionTable::new(-4, -3, -1);
+        table.insert("A1".to_string(), "A1".to_string(), 5);
+        table.insert("A1".to_string(), "M1".to_string(), 3);
+        assert_eq!(score(&table, "A1", "A1"), 5);
+        assert_eq!(score(&table, "M1", "A1"), 3);
+        assert_eq!(score(&table, "M1", "M1"), -4);
+        assert_eq!((table.gap_open, table.gap_extend), (-3, -1));
+    }
+
+    #[test]
+    fn edit_distance_test() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(EditDistance::similarity("ATTCC", "ATTCG"), 0.8);
+
+        let scorer = EditDistance::new(Scoring::new(4, -4, 0, -2), 0.7);
+        assert_eq!(score(&scorer, "ATTCC", "ATTCC"), 4);
+        // 0.8 of the way from the mismatch to the match score
+        assert_eq!(score(&scorer, "ATTCC", "ATTCG"), 2);
+        assert_eq!(score(&scorer, "ATTCC", "AGGCG"), -4);
+        assert!(scorer.is_match(&"ATTCC".to_string(), &"ATTCG".to_string()));
+        assert!(!scorer.is_match(&"ATTCC".to_string(), &"AGGCG".to_string()));
+    }
+}
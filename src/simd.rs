@@ -0,0 +1,442 @@
+use crate::alignment::NEG_INF;
+
+#[cfg(test)]
+thread_local! {
+    /// Instruction set tests align with instead of the detected one
+    static FORCED: std::cell::Cell<Option<Simd>> = const { std::cell::Cell::new(None) };
+}
+
+/// Instruction set used for the whole row operations of the DP. Every
+/// variant gives exactly the same results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Simd {
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+    #[cfg(target_arch = "x86_64")]
+    Sse41,
+    /// Portable fallback
+    Scalar,
+}
+
+impl Simd {
+    /// Fastest instruction set supported by the running CPU
+    pub fn detect() -> Self {
+        #[cfg(test)]
+        if let Some(simd) = FORCED.with(|forced| forced.get()) {
+            return simd;
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return Simd::Avx2;
+            }
+            if is_x86_feature_detected!("sse4.1") {
+                return Simd::Sse41;
+            }
+        }
+        Simd::Scalar
+    }
+
+    /// Use the instruction set on this thread instead of detecting one
+    #[cfg(test)]
+    pub fn force(simd: Option<Simd>) {
+        FORCED.with(|forced| forced.set(simd));
+    }
+
+    /// Every instruction set the running CPU supports
+    #[cfg(test)]
+    pub fn supported() -> Vec<Simd> {
+        let mut levels = vec![Simd::Scalar];
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("sse4.1") {
+                levels.push(Simd::Sse41);
+            }
+            if is_x86_feature_detected!("avx2") {
+                levels.push(Simd::Avx2);
+            }
+        }
+        levels
+    }
+
+    /// `target[k] = max(target[k], best[k] + open, deletions[k] + extend)`
+    pub fn max_deletion(
+        self,
+        target: &mut [i32],
+        best: &[i32],
+        deletions: &[i32],
+        open: i32,
+        extend: i32,
+    ) {
+        let (best, deletions) = (&best[..target.len()], &deletions[..target.len()]);
+        // the vector kernels handle whole lanes and leave the rest
+        let done = match self {
+            #[cfg(target_arch = "x86_64")]
+            Simd::Avx2 => unsafe { avx2::max_deletion(target, best, deletions, open, extend) },
+            #[cfg(target_arch = "x86_64")]
+            Simd::Sse41 => unsafe { sse41::max_deletion(target, best, deletions, open, extend) },
+            Simd::Scalar => 0,
+        };
+        scalar::max_deletion(
+            &mut target[done..],
+            &best[done..],
+            &deletions[done..],
+            open,
+            extend,
+        );
+    }
+
+    /// `target[k] = max(target[k], source[k])`
+    pub fn max_assign(self, target: &mut [i32], source: &[i32]) {
+        let source = &source[..target.len()];
+        let done = match self {
+            #[cfg(target_arch = "x86_64")]
+            Simd::Avx2 => unsafe { avx2::max_assign(target, source) },
+            #[cfg(target_arch = "x86_64")]
+            Simd::Sse41 => unsafe { sse41::max_assign(target, source) },
+            Simd::Scalar => 0,
+        };
+        scalar::max_assign(&mut target[done..], &source[done..]);
+    }
+
+    /// `target[k] += source[k]`
+    pub fn add_assign(self, target: &mut [i32], source: &[i32]) {
+        let source = &source[..target.len()];
+        let done = match self {
+            #[cfg(target_arch = "x86_64")]
+            Simd::Avx2 => unsafe { avx2::add_assign(target, source) },
+            #[cfg(target_arch = "x86_64")]
+            Simd::Sse41 => unsafe { sse41::add_assign(target, source) },
+            Simd::Scalar => 0,
+        };
+        scalar::add_assign(&mut target[done..], &source[done..]);
+    }
+
+    /// Run insertions along a row, `bests` holds the best of every other
+    /// move and is updated with the insertions:
+    /// `insertions[k] = max(bests[k - 1] + open, insertions[k - 1] + extend)`
+    /// and `bests[k] = max(bests[k], insertions[k])`, with no insertion into
+    /// the first column. When `open <= extend` an insertion never opens from
+    /// another, so they are the prefix maximum of
+    /// `bests[k] + open - extend * (k + 1)`, plus `extend * k`, which the
+    /// vector kernels compute a lane at a time.
+    pub fn insertions(self, insertions: &mut [i32], bests: &mut [i32], open: i32, extend: i32) {
+        let bests = &mut bests[..insertions.len()];
+        let done = match self {
+            _ if open > extend => 0,
+            #[cfg(target_arch = "x86_64")]
+            Simd::Avx2 => unsafe { avx2::insertions(insertions, bests, open, extend) },
+            #[cfg(target_arch = "x86_64")]
+            Simd::Sse41 => unsafe { sse41::insertions(insertions, bests, open, extend) },
+            Simd::Scalar => 0,
+        };
+        scalar::insertions(insertions, bests, open, extend, done);
+    }
+}
+
+mod scalar {
+    use std::cmp::max;
+
+    pub fn max_deletion(
+        target: &mut [i32],
+        best: &[i32],
+        deletions: &[i32],
+        open: i32,
+        extend: i32,
+    ) {
+        for ((target, &best), &deletion) in target.iter_mut().zip(best).zip(deletions) {
+            *target = max(*target, max(best + open, deletion + extend));
+        }
+    }
+
+    pub fn max_assign(target: &mut [i32], source: &[i32]) {
+        for (target, &source) in target.iter_mut().zip(source) {
+            *target = max(*target, source);
+        }
+    }
+
+    pub fn add_assign(target: &mut [i32], source: &[i32]) {
+        for (target, &source) in target.iter_mut().zip(source) {
+            *target += source;
+        }
+    }
+
+    /// Insertions from column `from` on, the columns before it are done
+    pub fn insertions(
+        insertions: &mut [i32],
+        bests: &mut [i32],
+        open: i32,
+        extend: i32,
+        from: usize,
+    ) {
+        for k in from..insertions.len() {
+            let insertion = if k > 0 {
+                max(bests[k - 1] + open, insertions[k - 1] + extend)
+            } else {
+                super::NEG_INF
+            };
+            insertions[k] = insertion;
+            bests[k] = max(bests[k], insertion);
+        }
+    }
+}
+
+/// Kernels over 8 lanes, they return how many leading elements they handled
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    /// # Safety
+    /// The CPU must support AVX2 and the slices must be as long as `target`
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn max_deletion(
+        target: &mut [i32],
+        best: &[i32],
+        deletions: &[i32],
+        open: i32,
+        extend: i32,
+    ) -> usize {
+        let lanes = target.len() / 8 * 8;
+        let (open, extend) = (_mm256_set1_epi32(open), _mm256_set1_epi32(extend));
+        for k in (0..lanes).step_by(8) {
+            let slot = target.as_mut_ptr().add(k) as *mut __m256i;
+            let best = _mm256_loadu_si256(best.as_ptr().add(k) as *const __m256i);
+            let deletion = _mm256_loadu_si256(deletions.as_ptr().add(k) as *const __m256i);
+            let candidate = _mm256_max_epi32(
+                _mm256_add_epi32(best, open),
+                _mm256_add_epi32(deletion, extend),
+            );
+            _mm256_storeu_si256(slot, _mm256_max_epi32(_mm256_loadu_si256(slot), candidate));
+        }
+        lanes
+    }
+
+    /// # Safety
+    /// The CPU must support AVX2 and `source` must be as long as `target`
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn max_assign(target: &mut [i32], source: &[i32]) -> usize {
+        let lanes = target.len() / 8 * 8;
+        for k in (0..lanes).step_by(8) {
+            let slot = target.as_mut_ptr().add(k) as *mut __m256i;
+            let source = _mm256_loadu_si256(source.as_ptr().add(k) as *const __m256i);
+            _mm256_storeu_si256(slot, _mm256_max_epi32(_mm256_loadu_si256(slot), source));
+        }
+        lanes
+    }
+
+    /// # Safety
+    /// The CPU must support AVX2 and `source` must be as long as `target`
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn add_assign(target: &mut [i32], source: &[i32]) -> usize {
+        let lanes = target.len() / 8 * 8;
+        for k in (0..lanes).step_by(8) {
+            let slot = target.as_mut_ptr().add(k) as *mut __m256i;
+            let source = _mm256_loadu_si256(source.as_ptr().add(k) as *const __m256i);
+            _mm256_storeu_si256(slot, _mm256_add_epi32(_mm256_loadu_si256(slot), source));
+        }
+        lanes
+    }
+
+    /// # Safety
+    /// The CPU must support AVX2, `bests` must be as long as `insertions`
+    /// and `open <= extend`
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn insertions(
+        insertions: &mut [i32],
+        bests: &mut [i32],
+        open: i32,
+        extend: i32,
+    ) -> usize {
+        let lanes = insertions.len() / 8 * 8;
+        let lowest = _mm256_set1_epi32(i32::MIN);
+        let extend_vector = _mm256_set1_epi32(extend);
+        let offset = _mm256_set1_epi32(open - extend);
+        let mut columns = _mm256_setr_epi32(0, 1, 2, 3, 4, 5, 6, 7);
+        // best of the earlier columns to open an insertion from
+        let mut carry = _mm256_set1_epi32(super::NEG_INF);
+        for k in (0..lanes).step_by(8) {
+            let slot = bests.as_mut_ptr().add(k) as *mut __m256i;
+            let best = _mm256_loadu_si256(slot);
+            let column_gaps = _mm256_mullo_epi32(columns, extend_vector);
+            let from = _mm256_sub_epi32(_mm256_add_epi32(best, offset), column_gaps);
+
+            // prefix maximum over the lanes, shifting by 1, 2 and 4 lanes
+            let mut prefix = from;
+            let shift =
+                _mm256_permutevar8x32_epi32(prefix, _mm256_setr_epi32(0, 0, 1, 2, 3, 4, 5, 6));
+            prefix = _mm256_max_epi32(prefix, _mm256_blend_epi32::<0b0000_0001>(shift, lowest));
+            let shift =
+                _mm256_permutevar8x32_epi32(prefix, _mm256_setr_epi32(0, 0, 0, 1, 2, 3, 4, 5));
+            prefix = _mm256_max_epi32(prefix, _mm256_blend_epi32::<0b0000_0011>(shift, lowest));
+            let shift =
+                _mm256_permutevar8x32_epi32(prefix, _mm256_setr_epi32(0, 0, 0, 0, 0, 1, 2, 3));
+            prefix = _mm256_max_epi32(prefix, _mm256_blend_epi32::<0b0000_1111>(shift, lowest));
+
+            // each column opens from the columns before it
+            let before =
+                _mm256_permutevar8x32_epi32(prefix, _mm256_setr_epi32(0, 0, 1, 2, 3, 4, 5, 6));
+            let before = _mm256_max_epi32(_mm256_blend_epi32::<0b0000_0001>(before, lowest), carry);
+            let insertion = _mm256_add_epi32(before, column_gaps);
+            _mm256_storeu_si256(insertions.as_mut_ptr().add(k) as *mut __m256i, insertion);
+            _mm256_storeu_si256(slot, _mm256_max_epi32(best, insertion));
+
+            let last = _mm256_permutevar8x32_epi32(prefix, _mm256_set1_epi32(7));
+            carry = _mm256_max_epi32(carry, last);
+            columns = _mm256_add_epi32(columns, _mm256_set1_epi32(8));
+        }
+        lanes
+    }
+}
+
+/// Kernels over 4 lanes, they return how many leading elements they handled
+#[cfg(target_arch = "x86_64")]
+mod sse41 {
+    use std::arch::x86_64::*;
+
+    /// # Safety
+    /// The CPU must support SSE4.1 and the slices must be as long as `target`
+    #[target_feature(enable = "sse4.1")]
+    pub unsafe fn max_deletion(
+        target: &mut [i32],
+        best: &[i32],
+        deletions: &[i32],
+        open: i32,
+        extend: i32,
+    ) -> usize {
+        let lanes = target.len() / 4 * 4;
+        let (open, extend) = (_mm_set1_epi32(open), _mm_set1_epi32(extend));
+        for k in (0..lanes).step_by(4) {
+            let slot = target.as_mut_ptr().add(k) as *mut __m128i;
+            let best = _mm_loadu_si128(best.as_ptr().add(k) as *const __m128i);
+            let deletion = _mm_loadu_si128(deletions.as_ptr().add(k) as *const __m128i);
+            let candidate =
+                _mm_max_epi32(_mm_add_epi32(best, open), _mm_add_epi32(deletion, extend));
+            _mm_storeu_si128(slot, _mm_max_epi32(_mm_loadu_si128(slot), candidate));
+        }
+        lanes
+    }
+
+    /// # Safety
+    /// The CPU must support SSE4.1 and `source` must be as long as `target`
+    #[target_feature(enable = "sse4.1")]
+    pub unsafe fn max_assign(target: &mut [i32], source: &[i32]) -> usize {
+        let lanes = target.len() / 4 * 4;
+        for k in (0..lanes).step_by(4) {
+            let slot = target.as_mut_ptr().add(k) as *mut __m128i;
+            let source = _mm_loadu_si128(source.as_ptr().add(k) as *const __m128i);
+            _mm_storeu_si128(slot, _mm_max_epi32(_mm_loadu_si128(slot), source));
+        }
+        lanes
+    }
+
+    /// # Safety
+    /// The CPU must support SSE4.1 and `source` must be as long as `target`
+    #[target_feature(enable = "sse4.1")]
+    pub unsafe fn add_assign(target: &mut [i32], source: &[i32]) -> usize {
+        let lanes = target.len() / 4 * 4;
+        for k in (0..lanes).step_by(4) {
+            let slot = target.as_mut_ptr().add(k) as *mut __m128i;
+            let source = _mm_loadu_si128(source.as_ptr().add(k) as *const __m128i);
+            _mm_storeu_si128(slot, _mm_add_epi32(_mm_loadu_si128(slot), source));
+        }
+        lanes
+    }
+
+    /// # Safety
+    /// The CPU must support SSE4.1, `bests` must be as long as `insertions`
+    /// and `open <= extend`
+    #[target_feature(enable = "sse4.1")]
+    pub unsafe fn insertions(
+        insertions: &mut [i32],
+        bests: &mut [i32],
+        open: i32,
+        extend: i32,
+    ) -> usize {
+        let lanes = insertions.len() / 4 * 4;
+        let lowest = _mm_set1_epi32(i32::MIN);
+        let extend_vector = _mm_set1_epi32(extend);
+        let offset = _mm_set1_epi32(open - extend);
+        let mut columns = _mm_setr_epi32(0, 1, 2, 3);
+        // best of the earlier columns to open an insertion from
+        let mut carry = _mm_set1_epi32(super::NEG_INF);
+        for k in (0..lanes).step_by(4) {
+            let slot = bests.as_mut_ptr().add(k) as *mut __m128i;
+            let best = _mm_loadu_si128(slot);
+            let column_gaps = _mm_mullo_epi32(columns, extend_vector);
+            let from = _mm_sub_epi32(_mm_add_epi32(best, offset), column_gaps);
+
+            // prefix maximum over the lanes, shifting in the lowest value
+            let mut prefix = from;
+            prefix = _mm_max_epi32(prefix, _mm_alignr_epi8::<12>(prefix, lowest));
+            prefix = _mm_max_epi32(prefix, _mm_alignr_epi8::<8>(prefix, lowest));
+
+            // each column opens from the columns before it
+            let before = _mm_max_epi32(_mm_alignr_epi8::<12>(prefix, lowest), carry);
+            let insertion = _mm_add_epi32(before, column_gaps);
+            _mm_storeu_si128(insertions.as_mut_ptr().add(k) as *mut __m128i, insertion);
+            _mm_storeu_si128(slot, _mm_max_epi32(best, insertion));
+
+            carry = _mm_max_epi32(carry, _mm_shuffle_epi32::<0b1111_1111>(prefix));
+            columns = _mm_add_epi32(columns, _mm_set1_epi32(4));
+        }
+        lanes
+    }
+}
+
+#[cfg(test)]
+mod simd_tests {
+    use super::{Simd, NEG_INF};
+    use crate::test_utils::Rng;
+
+    #[test]
+    fn kernels_match_scalar_test() {
+        // small scores, some NEG_INF
+        let mut rng = Rng::new(17);
+        let mut random = || {
+            let score = rng.below(200) as i32 - 100;
+            if rng.below(7) == 0 {
+                score + NEG_INF
+            } else {
+                score
+            }
+        };
+
+        // odd lengths exercise the scalar tails of the vector kernels
+        for len in [0, 1, 3, 4, 7, 8, 9, 17, 64, 101] {
+            let target: Vec<i32> = (0..len).map(|_| random()).collect();
+            let best: Vec<i32> = (0..len + 2).map(|_| random()).collect();
+            let deletions: Vec<i32> = (0..len + 2).map(|_| random()).collect();
+            let scores: Vec<i32> = (0..len).map(|k| k as i32 % 11 - 5).collect();
+
+            let mut expected_deletion = target.clone();
+            Simd::Scalar.max_deletion(&mut expected_deletion, &best, &deletions, -3, -1);
+            let mut expected_max = target.clone();
+            Simd::Scalar.max_assign(&mut expected_max, &best);
+            let mut expected_sum = target.clone();
+            Simd::Scalar.add_assign(&mut expected_sum, &scores);
+
+            for simd in Simd::supported() {
+                let mut deletion = target.clone();
+                simd.max_deletion(&mut deletion, &best, &deletions, -3, -1);
+                assert_eq!(deletion, expected_deletion, "{:?}", simd);
+                let mut maximum = target.clone();
+                simd.max_assign(&mut maximum, &best);
+                assert_eq!(maximum, expected_max, "{:?}", simd);
+                let mut sum = target.clone();
+                simd.add_assign(&mut sum, &scores);
+                assert_eq!(sum, expected_sum, "{:?}", simd);
+            }
+
+            // affine, linear and gap open bonus penalties
+            for (open, extend) in [(-5, -1), (-2, -2), (1, -2)] {
+                let mut expected = (vec![0; len], best[..len].to_vec());
+                Simd::Scalar.insertions(&mut expected.0, &mut expected.1, open, extend);
+                for simd in Simd::supported() {
+                    let mut scanned = (vec![0; len], best[..len].to_vec());
+                    simd.insertions(&mut scanned.0, &mut scanned.1, open, extend);
+                    assert_eq!(scanned, expected, "{:?} {}", simd, open);
+                }
+            }
+        }
+    }
+}
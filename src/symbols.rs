@@ -0,0 +1,192 @@
+use crate::scoring::Scorer;
+use std::{collections::HashMap, ops::Index};
+
+/// Interns tokens to dense integer ids so that alignment compares and
+/// stores integers instead of strings
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    tokens: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Id of the token, assigning the next free id to unseen tokens
+    pub fn intern(&mut self, token: &str) -> u32 {
+        if let Some(&id) = self.ids.get(token) {
+            return id;
+        }
+        let id = self.tokens.len() as u32;
+        self.tokens.push(token.to_string());
+        self.ids.insert(token.to_string(), id);
+        id
+    }
+
+    pub fn intern_seq(&mut self, seq: &[String]) -> Vec<u32> {
+        seq.iter().map(|token| self.intern(token)).collect()
+    }
+
+    /// Number of distinct tokens
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn resolve(&self, id: u32) -> &String {
+        &self.tokens[id as usize]
+    }
+
+    /// Length of the longest token
+    pub fn width(&self) -> usize {
+        self.tokens
+            .iter()
+            .map(|token| token.len())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl Index<u32> for SymbolTable {
+    type Output = String;
+
+    fn index(&self, id: u32) -> &String {
+        self.resolve(id)
+    }
+}
+
+/// Pairs of tokens up to which `Interned` scores every pair when it is built
+const TABLE_PAIRS: usize = 1 << 20;
+
+/// Scores interned tokens with a scorer for the original strings. For a
+/// small vocabulary the score and match of every pair of tokens are
+/// computed when it is built so that aligning only looks them up, larger
+/// ones are scored as they are compared. Tokens interned later cannot be
+/// scored.
+#[derive(Debug, Clone)]
+pub struct Interned<S> {
+    scorer: S,
+    tokens: Vec<String>,
+    /// Score and match of each pair, indexed by `a * tokens.len() + b`
+    table: Option<Vec<(i32, bool)>>,
+}
+
+impl<S: Scorer> Interned<S> {
+    pub fn new(scorer: S, symbols: &SymbolTable) -> Self {
+        let mut interned = Self {
+            scorer,
+            tokens: symbols.tokens.clone(),
+            table: None,
+        };
+        let width = interned.tokens.len();
+        if width * width <= TABLE_PAIRS {
+            let table = (0..width as u32)
+                .flat_map(|a| (0..width as u32).map(move |b| (a, b)))
+                .map(|(a, b)| (interned.score(&a, &b), interned.is_match(&a, &b)))
+                .collect();
+            interned.table = Some(table);
+        }
+        interned
+    }
+
+    fn pair(&self, a: u32, b: u32) -> Option<(i32, bool)> {
+        let table = self.table.as_ref()?;
+        Some(table[a as usize * self.tokens.len() + b as usize])
+    }
+}
+
+impl<S: Scorer> Scorer<u32> for Interned<S> {
+    fn score(&self, a: &u32, b: &u32) -> i32 {
+        match self.pair(*a, *b) {
+            Some((score, _)) => score,
+            None => self
+                .scorer
+                .score(&self.tokens[*a as usize], &self.tokens[*b as usize]),
+        }
+    }
+
+    fn gap_open(&self) -> i32 {
+        self.scorer.gap_open()
+    }
+
+    fn gap_extend(&self) -> i32 {
+        self.scorer.gap_extend()
+    }
+
+    fn is_match(&self, a: &u32, b: &u32) -> bool {
+        match self.pair(*a, *b) {
+            Some((_, is_match)) => is_match,
+            None => {
+                a == b
+                    || self
+                        .scorer
+                        .is_match(&self.tokens[*a as usize], &self.tokens[*b as usize])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod symbols_tests {
+    use super::*;
+    use crate::scoring::{CaseInsensitive, EditDistance, Scoring, SubstitutionTable};
+
+    #[test]
+    fn intern_test() {
+        let mut symbols = SymbolTable::new();
+        let seq = vec!["ACGT".to_string(), "TT".to_string(), "ACGT".to_string()];
+        assert_eq!(symbols.intern_seq(&seq), vec![0, 1, 0]);
+        assert_eq!(symbols.intern("acgt"), 2);
+        assert_eq!(symbols[1], "TT");
+        assert_eq!(symbols.width(), 4);
+
+        let scorer = Interned::new(CaseInsensitive::default(), &symbols);
+        assert_eq!(scorer.score(&0, &2), 1);
+        assert!(scorer.is_match(&0, &2));
+        assert!(!scorer.is_match(&0, &1));
+    }
+
+    #[test]
+    fn interned_scores_test() {
+        let mut symbols = SymbolTable::new();
+        let tokens = ["ATTCC", "ATTCG", "AGGCG", "A1", "M1"].map(str::to_string);
+        let ids = symbols.intern_seq(&tokens);
+
+        // the table agrees with the scorer on every pair of tokens
+        let fuzzy = EditDistance::new(Scoring::new(4, -4, 0, -2), 0.7);
+        let mut table = SubstitutionTable::new(-4, -3, -1);
+        table.insert("A1".to_string(), "M1".to_string(), 3);
+        let interned: [(Box<dyn Scorer<u32>>, &dyn Scorer); 2] = [
+            (Box::new(Interned::new(fuzzy, &symbols)), &fuzzy),
+            (Box::new(Interned::new(table.clone(), &symbols)), &table),
+        ];
+        for (interned, scorer) in &interned {
+            for (&a, x) in ids.iter().zip(&tokens) {
+                for (&b, y) in ids.iter().zip(&tokens) {
+                    assert_eq!(interned.score(&a, &b), scorer.score(x, y));
+                    assert_eq!(interned.is_match(&a, &b), x == y || scorer.is_match(x, y));
+                }
+            }
+            assert_eq!(interned.gap_open(), scorer.gap_open());
+        }
+        assert_eq!(interned[0].0.score(&ids[0], &ids[1]), 2);
+        assert!(interned[0].0.is_match(&ids[0], &ids[1]));
+        assert_eq!(interned[1].0.score(&ids[4], &ids[3]), 3);
+
+        // a large vocabulary is scored as tokens are compared
+        for i in 0..2000 {
+            symbols.intern(&format!("w{}", i));
+        }
+        let (a, b) = (symbols.intern("W7"), symbols.intern("w7"));
+        let scorer = Interned::new(CaseInsensitive::default(), &symbols);
+        assert!(scorer.table.is_none());
+        assert!(scorer.is_match(&a, &b));
+        assert_eq!(scorer.score(&a, &b), 1);
+        assert!(!scorer.is_match(&a, &ids[0]));
+    }
+}
@@ -0,0 +1,101 @@
+//! Fixtures shared by the tests of the other modules
+
+use crate::{
+    alignment::{AlignmentMode, AlignmentOptions, SeqGraphAlignment},
+    graph::POAGraph,
+    scoring::Scoring,
+};
+
+const BASES: [&str; 4] = ["A", "C", "G", "T"];
+
+pub const MODES: [AlignmentMode; 5] = [
+    AlignmentMode::Global,
+    AlignmentMode::Local,
+    AlignmentMode::Fitting,
+    AlignmentMode::Containment,
+    AlignmentMode::Overlap,
+];
+
+/// One token per character
+pub fn to_seq(s: &str) -> Vec<String> {
+    s.chars().map(|c| c.to_string()).collect()
+}
+
+/// Graph of the labelled sequences of `to_seq` tokens, added in order with
+/// global alignments of the default scoring
+pub fn graph_of(records: &[(&str, &str)]) -> POAGraph {
+    let records = records
+        .iter()
+        .map(|(label, seq)| (label.to_string(), to_seq(seq)))
+        .collect();
+    POAGraph::from_records(records, &Scoring::default(), AlignmentMode::Global.into()).unwrap()
+}
+
+/// Alignment of the `to_seq` tokens to the graph with the default scoring
+pub fn align(
+    graph: &POAGraph,
+    seq: &str,
+    options: impl Into<AlignmentOptions>,
+) -> SeqGraphAlignment {
+    graph
+        .align(
+            "query".to_string(),
+            to_seq(seq),
+            &Scoring::default(),
+            options.into(),
+        )
+        .unwrap()
+}
+
+/// Xorshift generator, the same seed gives the same numbers on every run
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift stays at zero
+        Self(seed.max(1))
+    }
+
+    /// Number below `n`
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+
+    pub fn base(&mut self) -> String {
+        BASES[self.below(4) as usize].to_string()
+    }
+
+    /// Bases of a length from 1 to `max_len`
+    pub fn seq(&mut self, max_len: u64) -> Vec<String> {
+        let length = 1 + self.below(max_len);
+        (0..length).map(|_| self.base()).collect()
+    }
+
+    /// Copy of the sequence with substitutions, deletions and insertions
+    /// each at one in `rate` of its tokens
+    pub fn mutate(&mut self, seq: &[String], rate: u64) -> Vec<String> {
+        let mut copy = Vec::with_capacity(seq.len());
+        for token in seq {
+            match self.below(rate) {
+                0 => copy.push(self.base()),
+                1 => {}
+                2 => copy.extend([token.clone(), self.base()]),
+                _ => copy.push(token.clone()),
+            }
+        }
+        copy
+    }
+
+    /// Graph of 1 to `max_sequences` sequences from `seq(max_len)`, added
+    /// with global alignments of the default scoring
+    pub fn graph(&mut self, max_sequences: u64, max_len: u64) -> POAGraph {
+        let count = 1 + self.below(max_sequences);
+        let records = (0..count)
+            .map(|k| (format!("seq_{}", k), self.seq(max_len)))
+            .collect();
+        POAGraph::from_records(records, &Scoring::default(), AlignmentMode::Global.into()).unwrap()
+    }
+}